@@ -0,0 +1,13 @@
+-- Daily snapshot of each artist's top listener cities, scraped from the web player artist overview.
+create table "top_city" (
+  "artist_id" varchar(255) not null,
+  "date" date not null,
+  "rank" int not null,
+  "city" varchar(255) not null,
+  "country" varchar(255) not null,
+  "region" varchar(255) null,
+  "listeners" int not null,
+  constraint "top_city_pkey" primary key ("artist_id", "date", "rank")
+);
+
+alter table "top_city" add constraint "top_city_artist_id_foreign" foreign key ("artist_id") references "artist" ("id") on update cascade on delete cascade;
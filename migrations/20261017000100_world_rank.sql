@@ -0,0 +1,9 @@
+-- Daily world rank of each artist, scraped from the web player artist overview.
+create table "world_rank" (
+  "artist_id" varchar(255) not null,
+  "date" date not null,
+  "rank" int not null,
+  constraint "world_rank_pkey" primary key ("artist_id", "date")
+);
+
+alter table "world_rank" add constraint "world_rank_artist_id_foreign" foreign key ("artist_id") references "artist" ("id") on update cascade on delete cascade;
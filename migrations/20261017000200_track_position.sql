@@ -0,0 +1,2 @@
+-- Position of each track on its album, backfilled as albums are re-scraped.
+alter table "track" add column "track_number" int null, add column "disc_number" int null;
@@ -0,0 +1,13 @@
+-- Recordings group every release of the same song (single, album, deluxe, compilation) by ISRC.
+create table "recording" (
+  "isrc" varchar(255) not null,
+  "name" varchar(255) not null,
+  constraint "recording_pkey" primary key ("isrc")
+);
+
+alter table "track" add column "isrc" varchar(255) null;
+alter table "track" add constraint "track_isrc_foreign" foreign key ("isrc") references "recording" ("isrc") on update cascade on delete set null;
+
+-- The stream date each track's isrc was last looked up, so tracks spotify has no isrc for are not
+-- requested again by every update.
+alter table "track" add column "isrc_checked" date null;
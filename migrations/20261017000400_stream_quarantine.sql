@@ -0,0 +1,12 @@
+-- Scraped playcounts that failed validation, held until an admin accepts or rejects them.
+create table "stream_quarantine" (
+  "track_id" varchar(255) not null,
+  "date" date not null,
+  "time" timestamptz not null,
+  "streams" bigint not null,
+  "reason" varchar(255) not null,
+  "status" varchar(255) not null default 'pending',
+  constraint "stream_quarantine_pkey" primary key ("track_id", "date")
+);
+
+alter table "stream_quarantine" add constraint "stream_quarantine_track_id_foreign" foreign key ("track_id") references "track" ("id") on update cascade on delete cascade;
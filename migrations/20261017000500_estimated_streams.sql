@@ -0,0 +1,2 @@
+-- Interpolated rows filling days the daily update missed are marked as estimated.
+alter table "daily_streams" add column "estimated" boolean not null default false;
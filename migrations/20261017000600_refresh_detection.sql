@@ -0,0 +1,8 @@
+-- When spotify's daily playcount refresh was detected for each stream day, and by how many sentinels.
+create table "refresh_detection" (
+  "date" date not null,
+  "detected_at" timestamptz not null,
+  "sentinels" int not null,
+  "moved" int not null,
+  constraint "refresh_detection_pkey" primary key ("date")
+);
@@ -0,0 +1,10 @@
+-- Checkpoints of the daily update, so a restarted update continues from the stage it stopped in.
+create table "update_run" (
+  "id" serial primary key,
+  "date" date not null,
+  "stage" varchar(255) not null,
+  "album_ids" text[] not null default '{}',
+  "albums_done" text[] not null default '{}',
+  "started_at" timestamptz not null,
+  "updated_at" timestamptz not null
+);
@@ -0,0 +1,26 @@
+-- History of each daily update: when it ended, what it updated, how long each stage took, and which
+-- albums failed.
+alter table "update_run" add column "ended_at" timestamptz null;
+alter table "update_run" add column "tracks_updated" int not null default 0;
+
+create table "update_run_stage" (
+  "run_id" int not null,
+  "stage" varchar(255) not null,
+  "started_at" timestamptz not null,
+  "ended_at" timestamptz not null,
+  "duration_ms" bigint not null,
+  constraint "update_run_stage_pkey" primary key ("run_id", "stage")
+);
+
+alter table "update_run_stage" add constraint "update_run_stage_run_id_foreign" foreign key ("run_id") references "update_run" ("id") on update cascade on delete cascade;
+
+create table "update_run_error" (
+  "id" serial primary key,
+  "run_id" int not null,
+  "stage" varchar(255) not null,
+  "album_id" varchar(255) not null,
+  "message" text not null,
+  "time" timestamptz not null
+);
+
+alter table "update_run_error" add constraint "update_run_error_run_id_foreign" foreign key ("run_id") references "update_run" ("id") on update cascade on delete cascade;
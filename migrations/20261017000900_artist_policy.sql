@@ -0,0 +1,13 @@
+-- Artists that may not be tracked (blocked) or may not be deleted (protected), edited through the
+-- admin policy endpoints. Seeded with the ids previously hard coded in create and delete artist.
+create table "artist_policy" (
+  "artist_id" varchar(255) not null,
+  "list" varchar(255) not null,
+  "reason" text null,
+  "created_at" timestamptz not null default now(),
+  constraint "artist_policy_pkey" primary key ("artist_id", "list")
+);
+
+insert into "artist_policy" ("artist_id", "list") values
+  ('5K4W6rqBFWDnAN6FQUkS6x', 'blocked'),
+  ('06HL4z0CvFAxyc27GXpf02', 'protected');
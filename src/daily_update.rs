@@ -1,11 +1,20 @@
 use std::error::Error;
 use stream_accumulator::modules;
+use tracing::{error, info};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    match modules::data_base::DB::daily_update().await {
-        Err(error) => println!("Error performing update: {}", error),
-        Ok(value) => println!("Update duration: {}", value),
+    let config = modules::config::install_from_args()?;
+    modules::logging::init(&config.logging);
+    if let Some(address) = config.metrics.listen.as_deref() {
+        modules::metrics::serve(address).await?;
+    }
+    modules::shutdown::listen();
+    let source = modules::union_source::from_config(&config.source)?;
+    let db = modules::data_base::DB::create().await?;
+    match db.daily_update(source.as_ref()).await {
+        Err(error) => error!(%error, "update not performed"),
+        Ok(value) => info!(duration = %value, "update finished"),
     }
     Ok(())
 }
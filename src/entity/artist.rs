@@ -22,6 +22,10 @@ pub enum Relation {
     FollowerInstance,
     #[sea_orm(has_many = "super::monthly_listeners::Entity")]
     MonthlyListeners,
+    #[sea_orm(has_many = "super::top_city::Entity")]
+    TopCity,
+    #[sea_orm(has_many = "super::world_rank::Entity")]
+    WorldRank,
 }
 
 impl Related<super::artist_albums::Entity> for Entity {
@@ -48,6 +52,18 @@ impl Related<super::monthly_listeners::Entity> for Entity {
     }
 }
 
+impl Related<super::top_city::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::TopCity.def()
+    }
+}
+
+impl Related<super::world_rank::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::WorldRank.def()
+    }
+}
+
 impl Related<super::album::Entity> for Entity {
     fn to() -> RelationDef {
         super::artist_albums::Relation::Album.def()
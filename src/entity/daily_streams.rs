@@ -11,6 +11,7 @@ pub struct Model {
     pub track_id: String,
     pub time: DateTimeWithTimeZone,
     pub streams: i64,
+    pub estimated: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -5,9 +5,18 @@ pub mod prelude;
 pub mod album;
 pub mod artist;
 pub mod artist_albums;
+pub mod artist_policy;
 pub mod artist_tracks;
 pub mod daily_streams;
 pub mod follower_instance;
 pub mod mikro_orm_migrations;
 pub mod monthly_listeners;
+pub mod recording;
+pub mod refresh_detection;
+pub mod stream_quarantine;
+pub mod top_city;
 pub mod track;
+pub mod update_run;
+pub mod update_run_error;
+pub mod update_run_stage;
+pub mod world_rank;
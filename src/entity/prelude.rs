@@ -3,9 +3,18 @@
 pub use super::album::Entity as Album;
 pub use super::artist::Entity as Artist;
 pub use super::artist_albums::Entity as ArtistAlbums;
+pub use super::artist_policy::Entity as ArtistPolicy;
 pub use super::artist_tracks::Entity as ArtistTracks;
 pub use super::daily_streams::Entity as DailyStreams;
 pub use super::follower_instance::Entity as FollowerInstance;
 pub use super::mikro_orm_migrations::Entity as MikroOrmMigrations;
 pub use super::monthly_listeners::Entity as MonthlyListeners;
+pub use super::recording::Entity as Recording;
+pub use super::refresh_detection::Entity as RefreshDetection;
+pub use super::stream_quarantine::Entity as StreamQuarantine;
+pub use super::top_city::Entity as TopCity;
 pub use super::track::Entity as Track;
+pub use super::update_run::Entity as UpdateRun;
+pub use super::update_run_error::Entity as UpdateRunError;
+pub use super::update_run_stage::Entity as UpdateRunStage;
+pub use super::world_rank::Entity as WorldRank;
@@ -11,6 +11,10 @@ pub struct Model {
     pub name: String,
     pub album_id: String,
     pub length: i32,
+    pub track_number: Option<i32>,
+    pub disc_number: Option<i32>,
+    pub isrc: Option<String>,
+    pub isrc_checked: Option<Date>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -27,6 +31,16 @@ pub enum Relation {
     ArtistTracks,
     #[sea_orm(has_many = "super::daily_streams::Entity")]
     DailyStreams,
+    #[sea_orm(
+        belongs_to = "super::recording::Entity",
+        from = "Column::Isrc",
+        to = "super::recording::Column::Isrc",
+        on_update = "Cascade",
+        on_delete = "SetNull"
+    )]
+    Recording,
+    #[sea_orm(has_many = "super::stream_quarantine::Entity")]
+    StreamQuarantine,
 }
 
 impl Related<super::album::Entity> for Entity {
@@ -47,6 +61,18 @@ impl Related<super::daily_streams::Entity> for Entity {
     }
 }
 
+impl Related<super::recording::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Recording.def()
+    }
+}
+
+impl Related<super::stream_quarantine::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::StreamQuarantine.def()
+    }
+}
+
 impl Related<super::artist::Entity> for Entity {
     fn to() -> RelationDef {
         super::artist_tracks::Relation::Artist.def()
@@ -1,12 +1,20 @@
 use crate::entity::{prelude::*, *};
+use crate::modules::error::AccumulatorError;
 use crate::modules::http_requests::{get_union, GetUnion};
-use crate::modules::{data_base, data_base::DB, track_union, track_union::SharingInfo};
+use crate::modules::metrics;
+use crate::modules::union_source::{UnionKind, UnionSource};
+use crate::modules::{data_base::DB, stream_day, track_union, track_union::SharingInfo};
 use async_trait::async_trait;
-use chrono::{DateTime, Local, TimeZone, Utc};
-use sea_orm::{sea_query::OnConflict, ActiveValue::Set, DbErr, EntityTrait, InsertResult};
+use chrono::{DateTime, NaiveDate};
+use sea_orm::{
+    sea_query::{Expr, OnConflict},
+    ActiveValue::Set,
+    ColumnTrait, DbErr, EntityTrait, InsertResult, PaginatorTrait, QueryFilter,
+};
 use serde::{Deserialize, Serialize};
 use serde_aux::field_attributes::deserialize_number_from_string;
-use std::{collections::HashSet, env, error::Error};
+use std::collections::{HashMap, HashSet};
+use tracing::warn;
 
 /// ArtistObject is used as part of Album union struct for deserializing JSON
 #[derive(Serialize, Deserialize, Debug)]
@@ -69,6 +77,10 @@ struct TrackDetail {
     name: String,
     #[serde(deserialize_with = "deserialize_number_from_string")]
     playcount: u64,
+    #[serde(default)]
+    disc_number: Option<u32>,
+    #[serde(default)]
+    track_number: Option<u32>,
     duration: Duration,
     artists: ArtistsObject,
 }
@@ -85,6 +97,8 @@ struct TrackObject {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct TracksObject {
+    #[serde(default)]
+    total_count: Option<usize>,
     items: Vec<TrackObject>,
 }
 
@@ -96,7 +110,7 @@ pub struct AlbumUnion {
     #[serde(alias = "__typename")]
     typename: String,
     uri: String,
-    name: String,
+    pub(crate) name: String,
     date: DateObject,
     #[serde(alias = "type")]
     album_type: String,
@@ -106,25 +120,104 @@ pub struct AlbumUnion {
     tracks: TracksObject,
 }
 
-/// The Album Union Implementation of the GetUnion Trait fetches the album from the aws endpoint I created
-/// which intercepts the network traffic from spotify's web player in order ot obtain the playcount
-/// for each track in the album (as well as other album and track details).
+/// The Album Union Implementation of the GetUnion Trait fetches the album from the union source
+/// provided (the aws endpoint I created, spotify's web player, or fixtures) in order ot obtain the
+/// playcount for each track in the album (as well as other album and track details).
 #[async_trait]
 impl GetUnion for AlbumUnion {
-    async fn get_union<'a>(id: &str) -> Result<Self, String> {
-        dotenv::dotenv().ok();
-        let key = env::var("ALBUM_END_POINT").unwrap();
-        get_union::<Self>(key.as_str(), id, "albumID").await
+    async fn get_union<'a>(source: &dyn UnionSource, id: &str) -> Result<Self, AccumulatorError> {
+        let mut union = get_union::<Self>(source, UnionKind::Album, id).await?;
+        union.fetch_remaining_tracks(source, id).await;
+        Ok(union)
     }
 }
 
 impl AlbumUnion {
+    /// Fetches and merges the pages of tracks missing from a truncated track list, logging when
+    /// the album is still incomplete.
+    async fn fetch_remaining_tracks(&mut self, source: &dyn UnionSource, id: &str) {
+        let total = match self.tracks.total_count {
+            None => return,
+            Some(value) => value,
+        };
+        while self.tracks.items.len() < total {
+            let page = source
+                .fetch_album_tracks(id, self.tracks.items.len())
+                .await
+                .and_then(|value| {
+                    serde_json::from_value::<TracksObject>(value).map_err(|error| {
+                        AccumulatorError::Parse(format!("album {} tracks: {}", id, error))
+                    })
+                });
+            match page {
+                Ok(page) if !page.items.is_empty() => self.tracks.items.extend(page.items),
+                Ok(_) => break,
+                Err(error) => {
+                    warn!(album_id = id, %error, "album tracks not fetched");
+                    break;
+                }
+            }
+        }
+        if self.tracks.items.len() != total {
+            warn!(
+                album_id = id,
+                fetched = self.tracks.items.len(),
+                total,
+                "album incomplete"
+            );
+        }
+    }
+
+    /// Returns the number of tracks fetched along with the total the album reports.
+    #[cfg(test)]
+    pub(crate) fn track_count(&self) -> (usize, Option<usize>) {
+        (self.tracks.items.len(), self.tracks.total_count)
+    }
+
+    /// Logs when the number of the album's tracks stored differs from the number of fetched tracks
+    /// credited to a tracked artist.
+    async fn check_completeness(
+        &self,
+        album_id: &str,
+        artist_map: &HashSet<String>,
+        db: &DB,
+    ) -> Result<(), DbErr> {
+        let expected = self
+            .tracks
+            .items
+            .iter()
+            .filter(|track| {
+                track
+                    .track
+                    .artists
+                    .items
+                    .iter()
+                    .any(|artist| artist_map.contains(get_id_from_uri(&artist.uri)))
+            })
+            .count();
+        let stored = Track::find()
+            .filter(track::Column::AlbumId.eq(album_id))
+            .count(&db.db)
+            .await? as usize;
+        if stored != expected {
+            warn!(
+                album_id,
+                stored,
+                expected,
+                reported = self.tracks.total_count,
+                "album stored fewer tracks than tracked"
+            );
+        }
+        Ok(())
+    }
+
     /// Ingests the album union information into the database
     pub async fn update(
         &self,
+        db: &DB,
         artist_map: &HashSet<String>,
-    ) -> Result<InsertResult<album::ActiveModel>, Box<dyn Error>> {
-        let db = DB::create().await?;
+        date: NaiveDate,
+    ) -> Result<InsertResult<album::ActiveModel>, AccumulatorError> {
         let images = self
             .cover_art
             .sources
@@ -144,7 +237,7 @@ impl AlbumUnion {
             images: Set(images),
             colors: Set(Some(serde_json::json!(&self.cover_art.extracted_colors))),
             display: Set(true),
-            updated: Set(Some(data_base::get_date(0).date_naive())),
+            updated: Set(Some(stream_day::run_date_for(date))),
             sharing_id: Set(self.sharing_info.share_id.to_owned()),
         };
 
@@ -169,20 +262,25 @@ impl AlbumUnion {
 
         for track in self.tracks.items.iter() {
             match track
-                .update(result.last_insert_id.as_str(), artist_map, &db)
+                .update(result.last_insert_id.as_str(), artist_map, db)
                 .await
             {
                 Ok(value) => connections.extend(value),
                 Err(error) => {
-                    println!("Error updating track {}: {}", track.track.name, error);
+                    warn!(
+                        track_id = get_id_from_uri(&track.track.uri),
+                        %error,
+                        "track not updated"
+                    );
                     continue;
                 }
             }
 
-            if let Err(error) = track.update_streams(&db).await {
-                println!(
-                    "Error updating track streams {}: {}",
-                    track.track.name, error
+            if let Err(error) = track.update_streams(db, date).await {
+                warn!(
+                    track_id = get_id_from_uri(&track.track.uri),
+                    %error,
+                    "track streams not updated"
                 );
             }
         }
@@ -209,18 +307,56 @@ impl AlbumUnion {
             .exec(&db.db)
             .await?;
 
+        if let Err(error) = self
+            .check_completeness(&result.last_insert_id, artist_map, db)
+            .await
+        {
+            warn!(album_id, %error, "album completeness not checked");
+        }
+
         Ok(result)
     }
 
-    /// Updates only the playcount for each track in the album.
-    pub async fn update_track_streams(&self) -> Result<bool, Box<dyn Error>> {
-        let db = DB::create().await?;
+    /// Updates only the playcount for each track in the album, along with the track and disc
+    /// number of stored tracks whose position has changed.
+    pub async fn update_track_streams(
+        &self,
+        db: &DB,
+        date: NaiveDate,
+    ) -> Result<bool, AccumulatorError> {
+        let positions = Track::find()
+            .filter(
+                track::Column::Id.is_in(
+                    self.tracks
+                        .items
+                        .iter()
+                        .map(|track| get_id_from_uri(&track.track.uri)),
+                ),
+            )
+            .all(&db.db)
+            .await?
+            .into_iter()
+            .map(|model| (model.id, (model.track_number, model.disc_number)))
+            .collect::<HashMap<String, (Option<i32>, Option<i32>)>>();
         let mut updated = 0;
         for track in self.tracks.items.iter() {
-            if let Err(error) = track.update_streams(&db).await {
-                println!(
-                    "Error updating track streams {}: {}",
-                    track.track.name, error
+            let moved = positions
+                .get(get_id_from_uri(&track.track.uri))
+                .is_some_and(|position| *position != track.position());
+            if moved {
+                if let Err(error) = track.update_position(db).await {
+                    warn!(
+                        track_id = get_id_from_uri(&track.track.uri),
+                        %error,
+                        "track position not updated"
+                    );
+                }
+            }
+            if let Err(error) = track.update_streams(db, date).await {
+                warn!(
+                    track_id = get_id_from_uri(&track.track.uri),
+                    %error,
+                    "track streams not updated"
                 );
             } else {
                 updated += 1;
@@ -265,6 +401,9 @@ impl TrackObject {
             album_id: Set(album_id.to_owned()),
             name: Set(self.track.name.to_owned()),
             length: Set(self.track.duration.total_milliseconds as i32),
+            track_number: Set(self.track.track_number.map(|number| number as i32)),
+            disc_number: Set(self.track.disc_number.map(|number| number as i32)),
+            ..Default::default()
         };
 
         Track::insert(active_track)
@@ -275,6 +414,8 @@ impl TrackObject {
                         track::Column::Name,
                         track::Column::Length,
                         track::Column::AlbumId,
+                        track::Column::TrackNumber,
+                        track::Column::DiscNumber,
                     ])
                     .to_owned(),
             )
@@ -297,40 +438,91 @@ impl TrackObject {
         Ok(connections_to_return)
     }
 
-    /// Ingests the track playcount into the database (if it is updated)
+    /// Returns the track and disc number of the track, as they are stored.
+    fn position(&self) -> (Option<i32>, Option<i32>) {
+        (
+            self.track.track_number.map(|number| number as i32),
+            self.track.disc_number.map(|number| number as i32),
+        )
+    }
+
+    /// Saves the track and disc number of the track, backfilling tracks stored before they were
+    /// recorded and correcting tracks moved within the album. A track the web player reports no
+    /// position for is left as it is.
+    async fn update_position(&self, db: &DB) -> Result<(), DbErr> {
+        if self.track.track_number.is_none() {
+            return Ok(());
+        }
+        let (track_number, disc_number) = self.position();
+        Track::update_many()
+            .col_expr(track::Column::TrackNumber, Expr::value(track_number))
+            .col_expr(track::Column::DiscNumber, Expr::value(disc_number))
+            .filter(track::Column::Id.eq(get_id_from_uri(&self.track.uri)))
+            .exec(&db.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Ingests the track playcount into the database (if it is updated) under the stream date
+    /// provided.
     async fn update_streams(
         &self,
         db: &DB,
-    ) -> Result<Option<InsertResult<daily_streams::ActiveModel>>, Box<dyn Error>> {
+        date: NaiveDate,
+    ) -> Result<Option<InsertResult<daily_streams::ActiveModel>>, AccumulatorError> {
         let track_id = get_id_from_uri(&self.track.uri);
+        let updates = &metrics::shared().stream_updates;
 
         match db
             .compare_streams(track_id, self.track.playcount)
             .await
             .unwrap_or_else(|error| {
-                println!("Error updating streams: {}", error);
+                warn!(track_id, %error, "streams not compared");
+                metrics::shared()
+                    .compare_streams
+                    .with_label_values(&["error"])
+                    .inc();
                 Some(false)
             }) {
             Some(true) => (),
-            _ => return Ok(None),
+            _ => {
+                updates.with_label_values(&["skipped"]).inc();
+                return Ok(None);
+            }
+        }
+
+        let time = stream_day::current().now();
+        let streams = self.track.playcount as i64;
+
+        if let Some(anomaly) = db.validate_streams(track_id, streams, date).await? {
+            warn!(track_id, streams, %anomaly, "streams quarantined");
+            db.quarantine_streams(track_id, date, time, streams, &anomaly)
+                .await?;
+            updates.with_label_values(&["quarantined"]).inc();
+            return Ok(None);
         }
 
         let active_daily_streams = daily_streams::ActiveModel {
-            date: Set(data_base::get_date(1).date_naive()),
+            date: Set(date),
             track_id: Set(track_id.to_owned()),
-            streams: Set(self.track.playcount as i64),
-            time: Set(chrono::Utc::now()
-                .with_timezone(&Local.offset_from_utc_date(&Utc::now().date_naive()))),
+            streams: Set(streams),
+            time: Set(time),
+            estimated: Set(false),
         };
 
         let result = DailyStreams::insert(active_daily_streams)
             .on_conflict(
                 OnConflict::columns([daily_streams::Column::Date, daily_streams::Column::TrackId])
-                    .update_columns([daily_streams::Column::Streams, daily_streams::Column::Time])
+                    .update_columns([
+                        daily_streams::Column::Streams,
+                        daily_streams::Column::Time,
+                        daily_streams::Column::Estimated,
+                    ])
                     .to_owned(),
             )
             .exec(&db.db)
             .await?;
+        updates.with_label_values(&["inserted"]).inc();
         Ok(Some(result))
     }
 }
@@ -340,12 +532,63 @@ pub(crate) fn get_id_from_uri(uri: &str) -> &str {
     uri.split(':').collect::<Vec<&str>>()[2]
 }
 
+#[cfg(test)]
+use crate::modules::union_source::FixtureSource;
 use tokio;
 #[tokio::test]
 async fn test_get_album_union() {
-    let union = AlbumUnion::get_union("51hV7ASoVjOVuIhbib79We")
+    let source = FixtureSource::new(format!("{}/fixtures/unions", env!("CARGO_MANIFEST_DIR")));
+    let union = AlbumUnion::get_union(&source, "51hV7ASoVjOVuIhbib79We")
         .await
         .ok()
         .unwrap();
     assert_eq!(union.name, "Anti-Hero (feat. Bleachers)");
+    let track = &union.tracks.items[1].track;
+    assert_eq!((track.disc_number, track.track_number), (Some(1), Some(2)));
+}
+
+#[tokio::test]
+async fn test_update_track_streams_positions() {
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult};
+    let source = FixtureSource::new(format!("{}/fixtures/unions", env!("CARGO_MANIFEST_DIR")));
+    let union = AlbumUnion::get_union(&source, "51hV7ASoVjOVuIhbib79We")
+        .await
+        .ok()
+        .unwrap();
+    let stored = |id: &str, track_number: Option<i32>, disc_number: Option<i32>| track::Model {
+        id: id.to_string(),
+        name: id.to_string(),
+        album_id: "51hV7ASoVjOVuIhbib79We".to_string(),
+        length: 200_000,
+        track_number,
+        disc_number,
+        isrc: None,
+        isrc_checked: None,
+    };
+    let db = DB {
+        db: MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results([[
+                stored("4D7BCuvgdJlYvlX5WlN54t", Some(1), Some(1)),
+                stored("7Eb9KO7l6Qt8skHG9oRQBD", None, None),
+            ]])
+            .append_exec_results([MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 1,
+            }])
+            .into_connection(),
+    };
+    union
+        .update_track_streams(&db, NaiveDate::from_ymd_opt(2023, 11, 9).unwrap())
+        .await
+        .unwrap();
+
+    let updates = db
+        .db
+        .into_transaction_log()
+        .iter()
+        .map(|transaction| format!("{:?}", transaction))
+        .filter(|statement| statement.contains(r#"UPDATE \"track\""#))
+        .collect::<Vec<String>>();
+    assert_eq!(updates.len(), 1);
+    assert!(updates[0].contains("7Eb9KO7l6Qt8skHG9oRQBD"));
 }
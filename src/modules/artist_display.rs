@@ -1,21 +1,129 @@
 use crate::entity::album::Entity as Album;
+use crate::entity::prelude::{
+    DailyStreams, FollowerInstance, MonthlyListeners, Recording, TopCity, WorldRank,
+};
 use crate::entity::track::Entity as Track;
-use crate::entity::{album, daily_streams, track};
+use crate::entity::{
+    album, daily_streams, follower_instance, monthly_listeners, top_city, track, world_rank,
+};
 use crate::modules::album_union::ExtractedColors;
 use crate::modules::data_base::DB;
+use crate::modules::error::AccumulatorError;
+use crate::modules::stream_day;
 use crate::modules::track_union::Image;
-use chrono::NaiveDate as Date;
+use chrono::{Days, NaiveDate as Date};
 use futures::future;
-use sea_orm::{DbErr, ModelTrait};
+use sea_orm::{ColumnTrait, DbErr, EntityTrait, ModelTrait, QueryFilter, QueryOrder};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use tracing::warn;
 
 /// The TrackRow Struct is used to hold the track representation used in the AlbumDisplay struct.
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct TrackRow {
     name: String,
+    track_number: Option<i32>,
+    disc_number: Option<i32>,
     total: Option<i64>,
     difference_day: Option<i64>,
     difference_week: Option<i64>,
+    estimated: bool,
+}
+
+/// The ListenerRow Struct holds an artist's monthly listeners and followers for a single date.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ListenerRow {
+    date: Date,
+    listeners: Option<i32>,
+    followers: Option<i32>,
+}
+
+/// The CityPoint Struct holds a city's rank and listeners for a single date.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct CityPoint {
+    date: Date,
+    rank: i32,
+    listeners: i32,
+}
+
+/// The CityDisplay Struct holds the rank and listener history of one of an artist's top cities.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct CityDisplay {
+    city: String,
+    country: String,
+    region: Option<String>,
+    history: Vec<CityPoint>,
+}
+
+/// The LeaderboardRow Struct holds a tracked artist's current world rank and how many places it
+/// has moved over the last week and month, positive changes are climbs.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct LeaderboardRow {
+    id: String,
+    name: String,
+    rank: Option<i32>,
+    change_week: Option<i32>,
+    change_month: Option<i32>,
+}
+
+/// The LeaderboardSort enum selects the column the leaderboard is ordered by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LeaderboardSort {
+    Rank,
+    Week,
+    Month,
+}
+
+impl LeaderboardSort {
+    /// Parses the sort query parameter, sorting by rank when none is given. Anything other than
+    /// rank, week, or month is invalid.
+    pub fn parse(sort: Option<&str>) -> Result<Self, AccumulatorError> {
+        match sort {
+            None | Some("rank") => Ok(LeaderboardSort::Rank),
+            Some("week") => Ok(LeaderboardSort::Week),
+            Some("month") => Ok(LeaderboardSort::Month),
+            Some(sort) => Err(AccumulatorError::Validation(format!(
+                "leaderboard sort {} must be rank, week, or month",
+                sort
+            ))),
+        }
+    }
+}
+
+/// The DiscDisplay Struct groups the tracks of one disc of a multi-disc album.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct DiscDisplay {
+    disc_number: i32,
+    tracks: Vec<TrackRow>,
+}
+
+/// The RecordingRelease Struct holds one release of a recording along with its streams.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RecordingRelease {
+    track_id: String,
+    album_id: String,
+    #[serde(flatten)]
+    track: TrackRow,
+}
+
+/// The RecordingDisplay Struct holds every release of a song, with streams summed across them.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RecordingDisplay {
+    isrc: String,
+    name: String,
+    releases: Vec<RecordingRelease>,
+    total: i64,
+    difference_day: i64,
+    difference_week: i64,
+}
+
+/// The StreamPoint Struct holds a recording's streams summed across the releases recorded on a
+/// single date.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct StreamPoint {
+    date: Date,
+    streams: i64,
+    releases: usize,
 }
 
 /// The AlbumDisplay Struct is used to hold the album representation to be output.
@@ -28,6 +136,8 @@ pub struct AlbumDisplay {
     images: Vec<Image>,
     sharing_id: String,
     tracks: Vec<TrackRow>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    discs: Vec<DiscDisplay>,
     total: i64,
     difference_day: i64,
     difference_week: i64,
@@ -43,8 +153,7 @@ pub struct ArtistDisplay {
 
 impl ArtistDisplay {
     /// Creates the ArtistDisplay struct to be output
-    pub(crate) async fn create_artist(id: &str) -> Result<Option<Self>, DbErr> {
-        let db = DB::create().await?;
+    pub(crate) async fn create_artist(db: &DB, id: &str) -> Result<Option<Self>, AccumulatorError> {
         let artist_option = db.get_artist_by_id(id).await?;
         if artist_option.is_none() {
             return Ok(None);
@@ -62,9 +171,9 @@ impl ArtistDisplay {
             .collect::<Vec<Image>>();
         let mut albums_out = Vec::new();
         for album in albums.iter() {
-            match AlbumDisplay::create_album(&db, album).await {
+            match AlbumDisplay::create_album(db, album).await {
                 Ok(value) => albums_out.push(value),
-                Err(error) => println!("Error creating display album: {}", error),
+                Err(error) => warn!(album_id = %album.0.id, %error, "display album not created"),
             }
         }
 
@@ -97,6 +206,14 @@ impl AlbumDisplay {
             )
         };
         let mut track_rows = Vec::new();
+        let mut tracks = tracks.iter().collect::<Vec<&track::Model>>();
+        tracks.sort_by_key(|track| {
+            (
+                track.disc_number.unwrap_or(1),
+                track.track_number.unwrap_or(i32::MAX),
+                track.name.to_owned(),
+            )
+        });
 
         let response_bodies: Vec<Result<TrackRow, DbErr>> = future::join_all(
             tracks
@@ -129,7 +246,7 @@ impl AlbumDisplay {
                     };
                     track_rows.push(value)
                 }
-                Err(error) => println!("error creating track_row: {}", error),
+                Err(error) => warn!(%error, "track row not created"),
             }
         }
 
@@ -140,6 +257,7 @@ impl AlbumDisplay {
             colors,
             images,
             sharing_id: album.sharing_id.to_owned(),
+            discs: DiscDisplay::group(&track_rows),
             tracks: track_rows,
             total,
             difference_day,
@@ -154,35 +272,349 @@ impl TrackRow {
         let ds = db
             .get_daily_streams_by_track(track, daily_streams::Column::Date, 8)
             .await?;
+        let latest = ds.first();
+        // the rows recorded exactly the given number of calendar days before the latest row
+        let before = |days: u64| {
+            let date = latest?.date.checked_sub_days(Days::new(days))?;
+            ds.iter().find(|model| model.date == date)
+        };
+        let (day, week) = (before(1), before(7));
         Ok(Self {
             name: track.name.to_owned(),
-            total: if ds.is_empty() {
-                None
-            } else {
-                Some(ds[0].streams)
-            },
-            difference_day: if ds.len() < 2 {
-                None
-            } else {
-                Some(ds[0].streams - ds[1].streams)
-            },
-            difference_week: if ds.len() < 8 {
-                None
-            } else {
-                Some(ds[0].streams - ds[7].streams)
-            },
+            track_number: track.track_number,
+            disc_number: track.disc_number,
+            total: latest.map(|model| model.streams),
+            difference_day: latest
+                .zip(day)
+                .map(|(latest, day)| latest.streams - day.streams),
+            difference_week: latest
+                .zip(week)
+                .map(|(latest, week)| latest.streams - week.streams),
+            estimated: [latest, day, week]
+                .iter()
+                .flatten()
+                .any(|model| model.estimated),
         })
     }
 }
 
+impl RecordingDisplay {
+    /// Creates the RecordingDisplay struct to be output
+    pub(crate) async fn create_recording(db: &DB, isrc: &str) -> Result<Option<Self>, DbErr> {
+        let recording = match Recording::find_by_id(isrc).one(&db.db).await? {
+            None => return Ok(None),
+            Some(value) => value,
+        };
+        let tracks = recording.find_related(Track).all(&db.db).await?;
+        let rows = future::join_all(
+            tracks
+                .iter()
+                .map(|track| async move { TrackRow::create_row(db, track).await }),
+        )
+        .await;
+
+        let mut releases = Vec::new();
+        for (track, row) in tracks.iter().zip(rows) {
+            match row {
+                Ok(value) => releases.push(RecordingRelease {
+                    track_id: track.id.to_owned(),
+                    album_id: track.album_id.to_owned(),
+                    track: value,
+                }),
+                Err(error) => warn!(track_id = %track.id, %error, "track row not created"),
+            }
+        }
+
+        Ok(Some(Self {
+            total: releases
+                .iter()
+                .filter_map(|release| release.track.total)
+                .sum(),
+            difference_day: releases
+                .iter()
+                .filter_map(|release| release.track.difference_day)
+                .sum(),
+            difference_week: releases
+                .iter()
+                .filter_map(|release| release.track.difference_week)
+                .sum(),
+            isrc: recording.isrc,
+            name: recording.name,
+            releases,
+        }))
+    }
+}
+
+impl StreamPoint {
+    /// Creates a StreamPoint for every date any release of the recording has streams recorded.
+    pub(crate) async fn create_points(db: &DB, isrc: &str) -> Result<Vec<Self>, DbErr> {
+        let track_ids = Track::find()
+            .filter(track::Column::Isrc.eq(isrc))
+            .all(&db.db)
+            .await?
+            .into_iter()
+            .map(|track| track.id)
+            .collect::<Vec<String>>();
+        let streams = DailyStreams::find()
+            .filter(daily_streams::Column::TrackId.is_in(track_ids))
+            .all(&db.db)
+            .await?;
+
+        let mut points: BTreeMap<Date, Self> = BTreeMap::new();
+        for model in streams {
+            let point = points.entry(model.date).or_insert_with(|| Self {
+                date: model.date,
+                streams: 0,
+                releases: 0,
+            });
+            point.streams += model.streams;
+            point.releases += 1;
+        }
+        Ok(points.into_values().collect())
+    }
+}
+
+impl DiscDisplay {
+    /// Groups the ordered track rows by disc, albums with a single disc are not grouped.
+    fn group(track_rows: &[TrackRow]) -> Vec<Self> {
+        let mut discs: Vec<Self> = Vec::new();
+        for row in track_rows {
+            let disc_number = row.disc_number.unwrap_or(1);
+            match discs.last_mut() {
+                Some(disc) if disc.disc_number == disc_number => disc.tracks.push(row.clone()),
+                _ => discs.push(Self {
+                    disc_number,
+                    tracks: vec![row.clone()],
+                }),
+            }
+        }
+        if discs.len() < 2 {
+            discs.clear();
+        }
+        discs
+    }
+}
+
+impl ListenerRow {
+    /// Creates a ListenerRow for each date the artist has monthly listeners or followers recorded.
+    pub(crate) async fn create_rows(db: &DB, id: &str) -> Result<Vec<Self>, DbErr> {
+        let listeners = MonthlyListeners::find()
+            .filter(monthly_listeners::Column::ArtistId.eq(id))
+            .all(&db.db)
+            .await?;
+        let followers = FollowerInstance::find()
+            .filter(follower_instance::Column::ArtistId.eq(id))
+            .all(&db.db)
+            .await?;
+
+        let mut rows: BTreeMap<Date, Self> = BTreeMap::new();
+        for model in listeners {
+            rows.entry(model.date)
+                .or_insert_with(|| Self::empty(model.date))
+                .listeners = Some(model.listeners);
+        }
+        for model in followers {
+            rows.entry(model.date)
+                .or_insert_with(|| Self::empty(model.date))
+                .followers = Some(model.count);
+        }
+        Ok(rows.into_values().collect())
+    }
+
+    fn empty(date: Date) -> Self {
+        Self {
+            date,
+            listeners: None,
+            followers: None,
+        }
+    }
+}
+
+impl CityDisplay {
+    /// Creates a CityDisplay for every city the artist has ranked in, ordered by its most recent
+    /// rank.
+    pub(crate) async fn create_cities(db: &DB, id: &str) -> Result<Vec<Self>, DbErr> {
+        let snapshots = TopCity::find()
+            .filter(top_city::Column::ArtistId.eq(id))
+            .order_by_asc(top_city::Column::Date)
+            .all(&db.db)
+            .await?;
+
+        let mut cities: Vec<Self> = Vec::new();
+        for model in snapshots {
+            let point = CityPoint {
+                date: model.date,
+                rank: model.rank,
+                listeners: model.listeners,
+            };
+            match cities.iter_mut().find(|city| {
+                city.city == model.city
+                    && city.country == model.country
+                    && city.region == model.region
+            }) {
+                Some(city) => city.history.push(point),
+                None => cities.push(Self {
+                    city: model.city,
+                    country: model.country,
+                    region: model.region,
+                    history: vec![point],
+                }),
+            }
+        }
+        cities.sort_by_key(|city| {
+            city.history
+                .last()
+                .map(|point| (std::cmp::Reverse(point.date), point.rank))
+        });
+        Ok(cities)
+    }
+}
+
+impl LeaderboardRow {
+    /// Creates a LeaderboardRow for every tracked artist, artists without a rank (or without a
+    /// change when sorting by one) are listed last.
+    pub(crate) async fn create_rows(
+        db: &DB,
+        sort: LeaderboardSort,
+    ) -> Result<Vec<Self>, AccumulatorError> {
+        let artists = db.get_all_artists_standard(|artists| artists).await?;
+        let ranks = WorldRank::find()
+            .filter(
+                world_rank::Column::Date.gte(
+                    stream_day::current()
+                        .stream_date()
+                        .checked_sub_days(Days::new(2 * 30))
+                        .unwrap(),
+                ),
+            )
+            .all(&db.db)
+            .await?;
+
+        // Spotify reports no rank for artists that drop out of the ranking, so only ranks recorded
+        // on the latest date ranks were recorded for are current.
+        let latest = ranks
+            .iter()
+            .map(|model| model.date)
+            .max()
+            .unwrap_or_else(|| stream_day::current().stream_date());
+        let mut by_artist: HashMap<String, BTreeMap<Date, i32>> = HashMap::new();
+        for model in ranks {
+            by_artist
+                .entry(model.artist_id)
+                .or_default()
+                .insert(model.date, model.rank);
+        }
+
+        let mut rows = artists
+            .into_iter()
+            .map(|artist| {
+                let history = by_artist.remove(&artist.id).unwrap_or_default();
+                Self {
+                    change_week: rank_change(&history, latest, 7),
+                    change_month: rank_change(&history, latest, 30),
+                    rank: history.get(&latest).copied(),
+                    id: artist.id,
+                    name: artist.name,
+                }
+            })
+            .collect::<Vec<Self>>();
+        LeaderboardRow::sort(&mut rows, sort);
+        Ok(rows)
+    }
+
+    /// Orders the rows by the column provided, rows without a value in it are listed last.
+    fn sort(rows: &mut [Self], sort: LeaderboardSort) {
+        rows.sort_by_key(|row| match sort {
+            LeaderboardSort::Rank => (row.rank.is_none(), row.rank.unwrap_or_default()),
+            LeaderboardSort::Week => (
+                row.change_week.is_none(),
+                -row.change_week.unwrap_or_default(),
+            ),
+            LeaderboardSort::Month => (
+                row.change_month.is_none(),
+                -row.change_month.unwrap_or_default(),
+            ),
+        });
+    }
+}
+
+/// Returns how many places the rank recorded on the latest date has climbed since the latest rank
+/// recorded at least the days provided before it, none if the artist is unranked on the latest date.
+fn rank_change(history: &BTreeMap<Date, i32>, latest: Date, days: u64) -> Option<i32> {
+    let rank = history.get(&latest)?;
+    let (_, previous) = history
+        .range(..=latest.checked_sub_days(Days::new(days))?)
+        .next_back()?;
+    Some(previous - rank)
+}
+
 #[tokio::test]
 async fn test_create_artist() {
-    let result = ArtistDisplay::create_artist("06HL4z0CvFAxyc27GXpf02")
+    crate::modules::config::install_test();
+    let db = DB::create().await.unwrap();
+    let result = ArtistDisplay::create_artist(&db, "06HL4z0CvFAxyc27GXpf02")
         .await
         .ok()
         .unwrap();
     assert!(result.is_some());
     assert!(!result.unwrap().albums.is_empty());
-    let result = ArtistDisplay::create_artist("06HL4z0CvF").await.ok();
+    let result = ArtistDisplay::create_artist(&db, "06HL4z0CvF").await.ok();
     assert!(result.unwrap().is_none());
 }
+
+#[test]
+fn test_group_discs() {
+    let row = |disc_number: i32, track_number: i32| TrackRow {
+        name: format!("{}-{}", disc_number, track_number),
+        track_number: Some(track_number),
+        disc_number: Some(disc_number),
+        total: None,
+        difference_day: None,
+        difference_week: None,
+        estimated: false,
+    };
+    assert!(DiscDisplay::group(&[row(1, 1), row(1, 2)]).is_empty());
+    let discs = DiscDisplay::group(&[row(1, 1), row(1, 2), row(2, 1)]);
+    assert_eq!(discs.len(), 2);
+    assert_eq!(discs[0].tracks.len(), 2);
+    assert_eq!(discs[1].disc_number, 2);
+}
+
+#[test]
+fn test_leaderboard() {
+    assert_eq!(LeaderboardSort::parse(None).unwrap(), LeaderboardSort::Rank);
+    assert_eq!(
+        LeaderboardSort::parse(Some("month")).unwrap(),
+        LeaderboardSort::Month
+    );
+    assert!(matches!(
+        LeaderboardSort::parse(Some("year")),
+        Err(AccumulatorError::Validation(_))
+    ));
+
+    let date = |day: u32| Date::from_ymd_opt(2023, 11, day).unwrap();
+    let history = BTreeMap::from([(date(1), 40), (date(5), 30), (date(12), 25)]);
+    assert_eq!(rank_change(&history, date(12), 7), Some(5));
+    assert_eq!(rank_change(&history, date(12), 10), Some(15));
+    assert_eq!(rank_change(&history, date(12), 30), None);
+    assert_eq!(rank_change(&history, date(13), 7), None);
+
+    let row = |id: &str, rank: Option<i32>, change_week: Option<i32>| LeaderboardRow {
+        id: id.to_string(),
+        name: id.to_string(),
+        rank,
+        change_week,
+        change_month: None,
+    };
+    let mut rows = vec![
+        row("a", None, None),
+        row("b", Some(20), Some(-3)),
+        row("c", Some(10), Some(4)),
+    ];
+    LeaderboardRow::sort(&mut rows, LeaderboardSort::Rank);
+    let ids = |rows: &[LeaderboardRow]| rows.iter().map(|row| row.id.clone()).collect::<Vec<_>>();
+    assert_eq!(ids(&rows), ["c", "b", "a"]);
+    rows.push(row("d", Some(30), Some(9)));
+    LeaderboardRow::sort(&mut rows, LeaderboardSort::Week);
+    assert_eq!(ids(&rows), ["d", "c", "b", "a"]);
+}
@@ -0,0 +1,186 @@
+use crate::entity::{artist_policy, prelude::*};
+use crate::modules::config;
+use crate::modules::data_base::DB;
+use crate::modules::error::AccumulatorError;
+use crate::modules::stream_day;
+use chrono::{DateTime, FixedOffset};
+use sea_orm::{
+    sea_query::OnConflict, ColumnTrait, DbErr, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+use serde::Serialize;
+use std::fmt;
+
+/// The PolicyList enum names the two artist policy lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicyList {
+    /// Artists that may not be tracked.
+    Blocked,
+    /// Artists that may not be deleted.
+    Protected,
+}
+
+impl PolicyList {
+    /// Returns the name the list is saved under.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PolicyList::Blocked => "blocked",
+            PolicyList::Protected => "protected",
+        }
+    }
+
+    /// Parses a list name, anything other than blocked or protected is invalid.
+    pub fn parse(list: &str) -> Result<Self, AccumulatorError> {
+        match list {
+            "blocked" => Ok(PolicyList::Blocked),
+            "protected" => Ok(PolicyList::Protected),
+            _ => Err(AccumulatorError::Validation(format!(
+                "policy list {} must be blocked or protected",
+                list
+            ))),
+        }
+    }
+
+    /// Returns the ids placed on the list by the configuration.
+    fn configured(&self) -> &'static [String] {
+        let policy = &config::current().policy;
+        match self {
+            PolicyList::Blocked => &policy.blocked,
+            PolicyList::Protected => &policy.protected,
+        }
+    }
+}
+
+impl fmt::Display for PolicyList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The PolicyEntry struct describes an artist on a policy list. Configured entries come from the
+/// configuration and can only be removed there.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct PolicyEntry {
+    pub artist_id: String,
+    pub reason: Option<String>,
+    pub created_at: Option<DateTime<FixedOffset>>,
+    pub configured: bool,
+}
+
+/// The PolicyDisplay struct lists the artists on both policy lists.
+#[derive(Debug, Serialize)]
+pub struct PolicyDisplay {
+    pub blocked: Vec<PolicyEntry>,
+    pub protected: Vec<PolicyEntry>,
+}
+
+impl PolicyDisplay {
+    /// Creates the display of both policy lists, configured entries first.
+    pub(crate) async fn create_policy(db: &DB) -> Result<Self, DbErr> {
+        Ok(Self {
+            blocked: entries(db, PolicyList::Blocked).await?,
+            protected: entries(db, PolicyList::Protected).await?,
+        })
+    }
+}
+
+/// Returns the configured and saved entries on the list provided.
+async fn entries(db: &DB, list: PolicyList) -> Result<Vec<PolicyEntry>, DbErr> {
+    let mut entries = list
+        .configured()
+        .iter()
+        .map(|id| PolicyEntry {
+            artist_id: id.clone(),
+            reason: None,
+            created_at: None,
+            configured: true,
+        })
+        .collect::<Vec<PolicyEntry>>();
+    let saved = ArtistPolicy::find()
+        .filter(artist_policy::Column::List.eq(list.as_str()))
+        .order_by_asc(artist_policy::Column::CreatedAt)
+        .all(&db.db)
+        .await?;
+    entries.extend(
+        saved
+            .into_iter()
+            .filter(|entry| !list.configured().contains(&entry.artist_id))
+            .map(|entry| PolicyEntry {
+                artist_id: entry.artist_id,
+                reason: entry.reason,
+                created_at: Some(entry.created_at),
+                configured: false,
+            }),
+    );
+    Ok(entries)
+}
+
+/// Returns a policy error if the artist is on the list provided.
+pub(crate) async fn check(db: &DB, list: PolicyList, id: &str) -> Result<(), AccumulatorError> {
+    let listed = list.configured().iter().any(|value| value == id)
+        || ArtistPolicy::find_by_id((id.to_owned(), list.as_str().to_owned()))
+            .one(&db.db)
+            .await?
+            .is_some();
+    match (listed, list) {
+        (false, _) => Ok(()),
+        (true, PolicyList::Blocked) => Err(AccumulatorError::Policy(format!(
+            "artist {} is blocked from being tracked",
+            id
+        ))),
+        (true, PolicyList::Protected) => Err(AccumulatorError::Policy(format!(
+            "artist {} is protected from being deleted",
+            id
+        ))),
+    }
+}
+
+/// Adds the artist to the list provided, replacing the reason if it is already there.
+pub(crate) async fn add(
+    db: &DB,
+    list: PolicyList,
+    id: &str,
+    reason: Option<String>,
+) -> Result<(), DbErr> {
+    ArtistPolicy::insert(artist_policy::ActiveModel {
+        artist_id: Set(id.to_owned()),
+        list: Set(list.as_str().to_owned()),
+        reason: Set(reason),
+        created_at: Set(stream_day::current().now()),
+    })
+    .on_conflict(
+        OnConflict::columns([artist_policy::Column::ArtistId, artist_policy::Column::List])
+            .update_column(artist_policy::Column::Reason)
+            .to_owned(),
+    )
+    .exec(&db.db)
+    .await?;
+    Ok(())
+}
+
+/// Removes the artist from the list provided, returning false if it was not on the list. Entries
+/// from the configuration can not be removed.
+pub(crate) async fn remove(db: &DB, list: PolicyList, id: &str) -> Result<bool, AccumulatorError> {
+    if list.configured().iter().any(|value| value == id) {
+        return Err(AccumulatorError::Validation(format!(
+            "artist {} is {} by the configuration",
+            id, list
+        )));
+    }
+    let result = ArtistPolicy::delete_by_id((id.to_owned(), list.as_str().to_owned()))
+        .exec(&db.db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
+#[test]
+fn test_policy_list() {
+    assert_eq!(PolicyList::parse("blocked").unwrap(), PolicyList::Blocked);
+    assert_eq!(
+        PolicyList::parse(PolicyList::Protected.as_str()).unwrap(),
+        PolicyList::Protected
+    );
+    assert!(matches!(
+        PolicyList::parse("allowed"),
+        Err(AccumulatorError::Validation(_))
+    ));
+}
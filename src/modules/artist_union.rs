@@ -0,0 +1,140 @@
+use crate::modules::album_union::get_id_from_uri;
+use crate::modules::error::AccumulatorError;
+use crate::modules::http_requests::{get_union, GetUnion};
+#[cfg(test)]
+use crate::modules::union_source::FixtureSource;
+use crate::modules::union_source::{UnionKind, UnionSource};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// ReleaseObject is used as part of Artist union struct for deserializing JSON
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReleaseObject {
+    uri: String,
+}
+
+/// ReleasesObject is used as part of Artist union struct for deserializing JSON
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ReleasesObject {
+    items: Vec<ReleaseObject>,
+}
+
+/// AppearsOnItem is used as part of Artist union struct for deserializing JSON
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AppearsOnItem {
+    releases: ReleasesObject,
+}
+
+/// AppearsOnObject is used as part of Artist union struct for deserializing JSON
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct AppearsOnObject {
+    items: Vec<AppearsOnItem>,
+}
+
+/// RelatedContent is used as part of Artist union struct for deserializing JSON
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct RelatedContent {
+    appears_on: AppearsOnObject,
+}
+
+/// TopCity is used as part of Artist union struct for deserializing JSON
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TopCity {
+    pub(crate) number_of_listeners: u64,
+    pub(crate) city: String,
+    pub(crate) country: String,
+    pub(crate) region: Option<String>,
+}
+
+/// TopCitiesObject is used as part of Artist union struct for deserializing JSON
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TopCitiesObject {
+    items: Vec<TopCity>,
+}
+
+/// ArtistStats is used as part of Artist union struct for deserializing JSON
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ArtistStats {
+    monthly_listeners: Option<u64>,
+    world_rank: Option<u64>,
+    top_cities: Option<TopCitiesObject>,
+}
+
+/// The Artist Union struct is used to deserialize and ingest the artist overview scraped from
+/// the spotify web player.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtistUnion {
+    uri: String,
+    #[serde(default)]
+    stats: Option<ArtistStats>,
+    related_content: RelatedContent,
+}
+
+/// The Artist Union Implementation of the GetUnion Trait fetches the artist overview from the union
+/// source provided (the aws endpoint I created, spotify's web player, or fixtures).
+#[async_trait]
+impl GetUnion for ArtistUnion {
+    async fn get_union<'a>(source: &dyn UnionSource, id: &str) -> Result<Self, AccumulatorError> {
+        get_union::<Self>(source, UnionKind::Artist, id).await
+    }
+}
+
+impl ArtistUnion {
+    /// Returns the id of the artist.
+    pub(crate) fn id(&self) -> &str {
+        get_id_from_uri(&self.uri)
+    }
+
+    /// Returns the artist's monthly listeners, the aws endpoint does not provide them.
+    pub(crate) fn monthly_listeners(&self) -> Option<u64> {
+        self.stats.as_ref()?.monthly_listeners
+    }
+
+    /// Returns the artist's global rank, spotify reports 0 for artists outside the ranking.
+    pub(crate) fn world_rank(&self) -> Option<u64> {
+        self.stats.as_ref()?.world_rank.filter(|rank| *rank != 0)
+    }
+
+    /// Returns the artist's top listener cities, highest ranked first.
+    pub(crate) fn top_cities(&self) -> &[TopCity] {
+        self.stats
+            .as_ref()
+            .and_then(|stats| stats.top_cities.as_ref())
+            .map_or(&[], |top_cities| top_cities.items.as_slice())
+    }
+
+    /// Returns the ids of every album the artist appears on.
+    pub(crate) fn appears_on(&self) -> Vec<String> {
+        self.related_content
+            .appears_on
+            .items
+            .iter()
+            .flat_map(|item| item.releases.items.iter())
+            .map(|release| get_id_from_uri(&release.uri).to_string())
+            .collect()
+    }
+}
+
+#[tokio::test]
+async fn test_get_artist_union() {
+    let source = FixtureSource::new(format!("{}/fixtures/unions", env!("CARGO_MANIFEST_DIR")));
+    let union = ArtistUnion::get_union(&source, "06HL4z0CvFAxyc27GXpf02")
+        .await
+        .ok()
+        .unwrap();
+    assert_eq!(union.id(), "06HL4z0CvFAxyc27GXpf02");
+    assert_eq!(union.monthly_listeners(), Some(82713043));
+    assert_eq!(union.world_rank(), Some(2));
+    assert_eq!(union.appears_on().len(), 2);
+    assert_eq!(union.top_cities().len(), 5);
+    assert_eq!(union.top_cities()[0].city, "London");
+}
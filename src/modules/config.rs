@@ -0,0 +1,614 @@
+use crate::modules::error::AccumulatorError;
+use crate::modules::stream_day::StreamDay;
+use crate::modules::web_player;
+use serde::Deserialize;
+use std::{env, fs, path::Path, str::FromStr, sync::OnceLock};
+use tracing_subscriber::EnvFilter;
+
+/// The Config struct holds every setting used by the server and the daily update. It is loaded
+/// from a TOML file (every section and field is optional), then the environment variables
+/// documented on each field override the file, so existing .env deployments keep working.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub database: DatabaseConfig,
+    pub source: SourceConfig,
+    pub spotify: SpotifyConfig,
+    pub update: UpdateConfig,
+    pub status_check: StatusCheckConfig,
+    pub stream_day: StreamDayConfig,
+    pub server: ServerConfig,
+    pub policy: PolicyConfig,
+    pub logging: LoggingConfig,
+    pub metrics: MetricsConfig,
+    pub cassette: CassetteConfig,
+}
+
+/// The DatabaseConfig struct holds the database url and connection pool settings.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    /// DATABASE_URL
+    pub url: String,
+    /// DATABASE_MAX_CONNECTIONS
+    pub max_connections: u32,
+    /// DATABASE_CONNECT_TIMEOUT, in seconds.
+    pub connect_timeout: u64,
+    /// DATABASE_IDLE_TIMEOUT, in seconds.
+    pub idle_timeout: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            max_connections: 10,
+            connect_timeout: 8,
+            idle_timeout: 300,
+        }
+    }
+}
+
+/// The SourceConfig struct selects the union source and holds the endpoints it uses.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourceConfig {
+    /// UNION_SOURCE: lambda, scraper, or fixtures. When unset the lambda is used if the album
+    /// endpoint is set, otherwise the scraper.
+    pub kind: Option<String>,
+    /// ALBUM_END_POINT
+    pub album_end_point: Option<String>,
+    /// TRACK_END_POINT
+    pub track_end_point: Option<String>,
+    /// ARTIST_END_POINT
+    pub artist_end_point: Option<String>,
+    /// WEB_PLAYER_TOKEN_URL
+    pub web_player_token_url: String,
+    /// WEB_PLAYER_QUERY_URL
+    pub web_player_query_url: String,
+    /// UNION_FIXTURE_DIR
+    pub fixture_dir: Option<String>,
+    /// UNION_RECORD_DIR
+    pub record_dir: Option<String>,
+}
+
+impl Default for SourceConfig {
+    fn default() -> Self {
+        Self {
+            kind: None,
+            album_end_point: None,
+            track_end_point: None,
+            artist_end_point: None,
+            web_player_token_url: web_player::TOKEN_URL.to_string(),
+            web_player_query_url: web_player::QUERY_URL.to_string(),
+            fixture_dir: None,
+            record_dir: None,
+        }
+    }
+}
+
+impl SourceConfig {
+    /// Returns the name of the union source in use.
+    pub fn kind(&self) -> &str {
+        match self.kind.as_deref() {
+            Some(kind) => kind,
+            None if self.album_end_point.is_some() => "lambda",
+            None => "scraper",
+        }
+    }
+}
+
+/// The SpotifyConfig struct holds the spotify web api credentials and request limits.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpotifyConfig {
+    /// SPOTIFY_KEY, the client credentials form body sent to the token endpoint.
+    pub key: Option<String>,
+    /// SPOTIFY_REQUEST_BUDGET, requests per 30 seconds.
+    pub request_budget: usize,
+    /// SPOTIFY_MAX_RETRIES
+    pub max_retries: u32,
+}
+
+impl Default for SpotifyConfig {
+    fn default() -> Self {
+        Self {
+            key: None,
+            request_budget: 150,
+            max_retries: 5,
+        }
+    }
+}
+
+/// The UpdateConfig struct holds the daily update's concurrency and validation settings.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdateConfig {
+    /// UPDATE_CONCURRENCY, the number of albums updated at once.
+    pub concurrency: usize,
+    /// STREAM_JUMP_MULTIPLE, the multiple of the trailing average daily increase allowed before a
+    /// jump is quarantined.
+    pub stream_jump_multiple: f64,
+    /// STREAM_JUMP_FLOOR, the daily increase a jump must reach before it is quarantined.
+    pub stream_jump_floor: u64,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 50,
+            stream_jump_multiple: 10.0,
+            stream_jump_floor: 10_000,
+        }
+    }
+}
+
+/// The StatusCheckConfig struct holds the tracks watched to detect spotify's daily playcount
+/// refresh.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatusCheckConfig {
+    /// STATUS_CHECK_SONG_IDS (comma separated, falling back to STATUS_CHECK_SONG_ID)
+    pub song_ids: Vec<String>,
+    /// STATUS_CHECK_SAMPLE_SIZE, tracks sampled from the catalog on top of the configured ones.
+    pub sample_size: u64,
+    /// STATUS_CHECK_QUORUM, a simple majority when unset.
+    pub quorum: Option<usize>,
+}
+
+impl Default for StatusCheckConfig {
+    fn default() -> Self {
+        Self {
+            song_ids: Vec::new(),
+            sample_size: 5,
+            quorum: None,
+        }
+    }
+}
+
+/// The StreamDayConfig struct holds the timezone and cutoff hour stream days are counted in.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamDayConfig {
+    /// STREAM_DAY_TIMEZONE, an IANA timezone name.
+    pub timezone: String,
+    /// STREAM_DAY_CUTOFF_HOUR
+    pub cutoff_hour: u32,
+}
+
+impl Default for StreamDayConfig {
+    fn default() -> Self {
+        Self {
+            timezone: "UTC".to_string(),
+            cutoff_hour: 0,
+        }
+    }
+}
+
+/// The ServerConfig struct holds the address and port the server binds to.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// SERVER_ADDRESS
+    pub address: String,
+    /// SERVER_PORT
+    pub port: u16,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1".to_string(),
+            port: 8000,
+        }
+    }
+}
+
+/// The PolicyConfig struct holds the artists blocked from being tracked and protected from being
+/// deleted on top of those added through the admin policy endpoints.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PolicyConfig {
+    /// ARTIST_BLOCKLIST (comma separated)
+    pub blocked: Vec<String>,
+    /// ARTIST_PROTECTED (comma separated)
+    pub protected: Vec<String>,
+}
+
+/// The LoggingConfig struct selects how diagnostics are written.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// LOG_FORMAT: pretty or json.
+    pub format: String,
+    /// RUST_LOG, a level optionally followed by per-module levels, for example
+    /// `info,stream_accumulator::modules::http_requests=debug`.
+    pub level: String,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: "pretty".to_string(),
+            level: "info".to_string(),
+        }
+    }
+}
+
+/// The MetricsConfig struct holds where the daily update serves its metrics, the server always
+/// serves them on /metrics.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// METRICS_LISTEN, the address (for example 0.0.0.0:9100) the daily update serves /metrics on
+    /// while it runs. Nothing is served when unset.
+    pub listen: Option<String>,
+}
+
+/// The CassetteConfig struct selects the cassette every HTTP request is recorded to or replayed
+/// from. Requests go to the network when no mode is set.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CassetteConfig {
+    /// CASSETTE_MODE: record or replay.
+    pub mode: Option<String>,
+    /// CASSETTE_PATH, the cassette file.
+    pub path: Option<String>,
+}
+
+/// Returns the configuration the process was started with. If the binary did not install one the
+/// defaults are loaded, overridden by the environment and validated.
+pub fn current() -> &'static Config {
+    CONFIG.get_or_init(|| Config::load(None).unwrap_or_else(|error| panic!("{}", error)))
+}
+
+/// Installs the configuration tests run with, unless one is installed already: the defaults
+/// overridden by the environment, with a placeholder spotify key as the token request is replayed
+/// from cassettes, which never record it.
+#[cfg(test)]
+pub(crate) fn install_test() -> &'static Config {
+    CONFIG.get_or_init(|| {
+        let mut config =
+            Config::from_env(Config::default()).unwrap_or_else(|error| panic!("{}", error));
+        config
+            .spotify
+            .key
+            .get_or_insert_with(|| "grant_type=client_credentials".to_string());
+        config
+    })
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Installs the configuration used by the rest of the process, it must be called before anything
+/// reads the configuration.
+pub fn install(config: Config) -> Result<(), AccumulatorError> {
+    CONFIG
+        .set(config)
+        .map_err(|_| AccumulatorError::Validation("configuration is already installed".to_string()))
+}
+
+/// Loads the configuration from the file passed with `--config <path>` (if any) and installs it.
+pub fn install_from_args() -> Result<&'static Config, AccumulatorError> {
+    let args = env::args().collect::<Vec<String>>();
+    let path =
+        match args.iter().position(|arg| arg == "--config") {
+            None => None,
+            Some(index) => Some(args.get(index + 1).ok_or_else(|| {
+                AccumulatorError::Validation("--config requires a path".to_string())
+            })?),
+        };
+    install(Config::load(path.map(Path::new))?)?;
+    Ok(current())
+}
+
+impl Config {
+    /// Loads the configuration from the TOML file provided (the defaults if none is), applies the
+    /// environment overrides, and validates the result.
+    pub fn load(path: Option<&Path>) -> Result<Self, AccumulatorError> {
+        let config = match path {
+            None => Config::default(),
+            Some(path) => {
+                let contents = fs::read_to_string(path).map_err(|error| {
+                    AccumulatorError::Validation(format!(
+                        "config file {}: {}",
+                        path.display(),
+                        error
+                    ))
+                })?;
+                Config::parse(&contents)?
+            }
+        };
+        let config = Config::from_env(config)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parses the TOML configuration provided.
+    pub fn parse(contents: &str) -> Result<Self, AccumulatorError> {
+        toml::from_str::<Config>(contents)
+            .map_err(|error| AccumulatorError::Parse(format!("config: {}", error)))
+    }
+
+    /// Applies the environment variables (and .env) set on top of the configuration provided.
+    fn from_env(mut config: Config) -> Result<Self, AccumulatorError> {
+        dotenv::dotenv().ok();
+        let mut errors = Vec::new();
+        let database = &mut config.database;
+        override_string(&mut database.url, "DATABASE_URL");
+        override_parsed(
+            &mut database.max_connections,
+            "DATABASE_MAX_CONNECTIONS",
+            &mut errors,
+        );
+        override_parsed(
+            &mut database.connect_timeout,
+            "DATABASE_CONNECT_TIMEOUT",
+            &mut errors,
+        );
+        override_parsed(
+            &mut database.idle_timeout,
+            "DATABASE_IDLE_TIMEOUT",
+            &mut errors,
+        );
+
+        let source = &mut config.source;
+        override_option(&mut source.kind, "UNION_SOURCE");
+        override_option(&mut source.album_end_point, "ALBUM_END_POINT");
+        override_option(&mut source.track_end_point, "TRACK_END_POINT");
+        override_option(&mut source.artist_end_point, "ARTIST_END_POINT");
+        override_string(&mut source.web_player_token_url, "WEB_PLAYER_TOKEN_URL");
+        override_string(&mut source.web_player_query_url, "WEB_PLAYER_QUERY_URL");
+        override_option(&mut source.fixture_dir, "UNION_FIXTURE_DIR");
+        override_option(&mut source.record_dir, "UNION_RECORD_DIR");
+
+        let spotify = &mut config.spotify;
+        override_option(&mut spotify.key, "SPOTIFY_KEY");
+        override_parsed(
+            &mut spotify.request_budget,
+            "SPOTIFY_REQUEST_BUDGET",
+            &mut errors,
+        );
+        override_parsed(&mut spotify.max_retries, "SPOTIFY_MAX_RETRIES", &mut errors);
+
+        let update = &mut config.update;
+        override_parsed(&mut update.concurrency, "UPDATE_CONCURRENCY", &mut errors);
+        override_parsed(
+            &mut update.stream_jump_multiple,
+            "STREAM_JUMP_MULTIPLE",
+            &mut errors,
+        );
+        override_parsed(
+            &mut update.stream_jump_floor,
+            "STREAM_JUMP_FLOOR",
+            &mut errors,
+        );
+
+        let status_check = &mut config.status_check;
+        if let Ok(ids) =
+            env::var("STATUS_CHECK_SONG_IDS").or_else(|_| env::var("STATUS_CHECK_SONG_ID"))
+        {
+            status_check.song_ids = split_ids(&ids);
+        }
+        override_parsed(
+            &mut status_check.sample_size,
+            "STATUS_CHECK_SAMPLE_SIZE",
+            &mut errors,
+        );
+        if let Ok(value) = env::var("STATUS_CHECK_QUORUM") {
+            match value.parse::<usize>() {
+                Ok(quorum) => status_check.quorum = Some(quorum),
+                Err(_) => errors.push(format!("STATUS_CHECK_QUORUM {} is not a number", value)),
+            }
+        }
+
+        override_string(&mut config.stream_day.timezone, "STREAM_DAY_TIMEZONE");
+        override_parsed(
+            &mut config.stream_day.cutoff_hour,
+            "STREAM_DAY_CUTOFF_HOUR",
+            &mut errors,
+        );
+
+        if let Ok(ids) = env::var("ARTIST_BLOCKLIST") {
+            config.policy.blocked = split_ids(&ids);
+        }
+        if let Ok(ids) = env::var("ARTIST_PROTECTED") {
+            config.policy.protected = split_ids(&ids);
+        }
+
+        override_string(&mut config.logging.format, "LOG_FORMAT");
+        override_string(&mut config.logging.level, "RUST_LOG");
+
+        override_option(&mut config.metrics.listen, "METRICS_LISTEN");
+
+        override_option(&mut config.cassette.mode, "CASSETTE_MODE");
+        override_option(&mut config.cassette.path, "CASSETTE_PATH");
+
+        override_string(&mut config.server.address, "SERVER_ADDRESS");
+        override_parsed(&mut config.server.port, "SERVER_PORT", &mut errors);
+
+        match errors.is_empty() {
+            true => Ok(config),
+            false => Err(AccumulatorError::Validation(errors.join("; "))),
+        }
+    }
+
+    /// Checks the configuration is complete and consistent, reporting every problem found at once.
+    pub fn validate(&self) -> Result<(), AccumulatorError> {
+        let mut errors = Vec::new();
+        if self.database.url.is_empty() {
+            errors.push("database.url (DATABASE_URL) must be set".to_string());
+        }
+        if self.database.max_connections == 0 {
+            errors.push("database.max_connections must be at least 1".to_string());
+        }
+        match self.source.kind() {
+            "lambda" => {
+                for (value, name) in [
+                    (
+                        &self.source.album_end_point,
+                        "album_end_point (ALBUM_END_POINT)",
+                    ),
+                    (
+                        &self.source.track_end_point,
+                        "track_end_point (TRACK_END_POINT)",
+                    ),
+                    (
+                        &self.source.artist_end_point,
+                        "artist_end_point (ARTIST_END_POINT)",
+                    ),
+                ] {
+                    if value.is_none() {
+                        errors.push(format!("source.{} must be set for the lambda source", name));
+                    }
+                }
+            }
+            "scraper" => (),
+            "fixtures" => {
+                if self.source.fixture_dir.is_none() {
+                    errors.push(
+                        "source.fixture_dir (UNION_FIXTURE_DIR) must be set for the fixtures source"
+                            .to_string(),
+                    );
+                }
+            }
+            kind => errors.push(format!(
+                "source.kind (UNION_SOURCE) {} must be lambda, scraper, or fixtures",
+                kind
+            )),
+        }
+        if self.spotify.key.is_none() {
+            errors.push("spotify.key (SPOTIFY_KEY) must be set".to_string());
+        }
+        if self.spotify.request_budget == 0 {
+            errors.push("spotify.request_budget must be at least 1".to_string());
+        }
+        if self.update.concurrency == 0 {
+            errors.push("update.concurrency must be at least 1".to_string());
+        }
+        if self.update.stream_jump_multiple <= 0.0 {
+            errors.push("update.stream_jump_multiple must be positive".to_string());
+        }
+        if self.status_check.song_ids.is_empty() && self.status_check.sample_size == 0 {
+            errors.push(
+                "status_check needs song_ids (STATUS_CHECK_SONG_IDS) or a sample_size".to_string(),
+            );
+        }
+        if !["pretty", "json"].contains(&self.logging.format.as_str()) {
+            errors.push(format!(
+                "logging.format (LOG_FORMAT) {} must be pretty or json",
+                self.logging.format
+            ));
+        }
+        if let Err(error) = EnvFilter::try_new(&self.logging.level) {
+            errors.push(format!(
+                "logging.level (RUST_LOG) {}: {}",
+                self.logging.level, error
+            ));
+        }
+        if let Some(mode) = self.cassette.mode.as_deref() {
+            if !["record", "replay"].contains(&mode) {
+                errors.push(format!(
+                    "cassette.mode (CASSETTE_MODE) {} must be record or replay",
+                    mode
+                ));
+            }
+            if self.cassette.path.is_none() {
+                errors.push("cassette.path (CASSETTE_PATH) must be set with a mode".to_string());
+            }
+        }
+        if let Err(error) = self.stream_day() {
+            errors.push(error.to_string());
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(AccumulatorError::Validation(format!(
+                "configuration: {}",
+                errors.join("; ")
+            ))),
+        }
+    }
+
+    /// Returns the stream day described by the configuration.
+    pub fn stream_day(&self) -> Result<StreamDay, AccumulatorError> {
+        StreamDay::parse(
+            &self.stream_day.timezone,
+            &self.stream_day.cutoff_hour.to_string(),
+        )
+    }
+}
+
+/// Splits the comma separated list of ids provided, ignoring blank entries.
+fn split_ids(ids: &str) -> Vec<String> {
+    ids.split(',')
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect()
+}
+
+/// Replaces the value with the environment variable provided, if it is set.
+fn override_string(value: &mut String, key: &str) {
+    if let Ok(var) = env::var(key) {
+        *value = var;
+    }
+}
+
+/// Replaces the optional value with the environment variable provided, if it is set.
+fn override_option(value: &mut Option<String>, key: &str) {
+    if let Ok(var) = env::var(key) {
+        *value = Some(var);
+    }
+}
+
+/// Replaces the value with the environment variable provided parsed, if it is set, recording an
+/// error if it does not parse.
+fn override_parsed<T: FromStr>(value: &mut T, key: &str, errors: &mut Vec<String>) {
+    if let Ok(var) = env::var(key) {
+        match var.parse::<T>() {
+            Ok(parsed) => *value = parsed,
+            Err(_) => errors.push(format!("{} {} is not valid", key, var)),
+        }
+    }
+}
+
+#[test]
+fn test_parse_config() {
+    let config = Config::parse(
+        r#"
+        [database]
+        url = "postgres://localhost/streams"
+        max_connections = 4
+
+        [source]
+        kind = "fixtures"
+        fixture_dir = "fixtures/unions"
+
+        [spotify]
+        key = "grant_type=client_credentials"
+
+        [status_check]
+        song_ids = ["7Eb9KO7l6Qt8skHG9oRQBD"]
+
+        [stream_day]
+        timezone = "America/Los_Angeles"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.database.max_connections, 4);
+    assert_eq!(config.database.idle_timeout, 300);
+    assert_eq!(config.source.kind(), "fixtures");
+    assert_eq!(config.update.concurrency, 50);
+    assert!(config.validate().is_ok());
+
+    assert!(Config::parse("[database]\nurl = 5").is_err());
+    assert!(Config::parse("[databse]\nurl = \"postgres://localhost\"").is_err());
+    let mut invalid = config.clone();
+    invalid.source.fixture_dir = None;
+    invalid.stream_day.cutoff_hour = 24;
+    invalid.cassette.mode = Some("rewind".to_string());
+    let error = invalid.validate().unwrap_err().to_string();
+    assert!(error.contains("UNION_FIXTURE_DIR"));
+    assert!(error.contains("cutoff hour"));
+    assert!(error.contains("CASSETTE_MODE"));
+    assert!(error.contains("CASSETTE_PATH"));
+}
@@ -1,31 +1,110 @@
 use crate::entity::{prelude::*, *};
 use crate::modules::album_union::AlbumUnion;
-use crate::modules::artist_display::{AlbumDisplay, ArtistDisplay};
-use crate::modules::http_requests::{get_artist_albums, get_artist_detail, get_data, GetUnion};
+use crate::modules::artist_display::{
+    AlbumDisplay, ArtistDisplay, CityDisplay, LeaderboardRow, LeaderboardSort, ListenerRow,
+    RecordingDisplay, StreamPoint,
+};
+use crate::modules::artist_policy::{self, PolicyDisplay, PolicyList};
+use crate::modules::artist_union::ArtistUnion;
+use crate::modules::config::{self, DatabaseConfig, StatusCheckConfig};
+use crate::modules::error::AccumulatorError;
+use crate::modules::http_requests::{
+    get_artist_albums, get_artist_detail, get_track_detail, ArtistAPI, GetUnion, TrackAPI,
+};
+use crate::modules::metrics;
+use crate::modules::refresh_detection::{
+    sentinel_state, tally, SentinelState, LOW_ACTIVITY_STREAMS,
+};
+use crate::modules::refresh_schedule::{remaining_wait, RefreshSchedule};
+use crate::modules::shutdown;
+use crate::modules::stream_day;
+use crate::modules::stream_gaps::interpolate_gaps;
+use crate::modules::stream_validation::{check_playcount, Anomaly};
 use crate::modules::track_union::TrackUnion;
+use crate::modules::union_source::UnionSource;
+use crate::modules::update_run::{RunDisplay, RunRow, RunStage, UpdateRun};
 use async_recursion::async_recursion;
-use chrono::{DateTime, Datelike, Days, Local, TimeZone, Utc};
+use chrono::{DateTime, Days, FixedOffset, NaiveDate, Utc};
 use futures::{future, stream, StreamExt};
 use sea_orm::{
-    sea_query::{OnConflict, Query},
-    ColumnTrait, Condition, Database, DatabaseConnection, DbErr, EntityTrait, IntoActiveModel,
-    ModelTrait, QueryFilter, QueryOrder, QuerySelect, Set,
+    sea_query::{Alias, Expr, JoinType, OnConflict, Query},
+    ActiveModelTrait, ColumnTrait, Condition, ConnectOptions, Database, DatabaseConnection, DbErr,
+    EntityTrait, IntoActiveModel, ModelTrait, Order, QueryFilter, QueryOrder, QuerySelect, Set,
+    TransactionTrait,
 };
-use std::{collections::HashSet, env, error::Error};
-use tokio::time::{sleep, Duration};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+use tracing::{debug, error, field, info, info_span, instrument, warn, Instrument, Span};
+
+/// The spotify web api accepts at most 50 artist ids per request.
+const ARTIST_CHUNK_SIZE: usize = 50;
+
+/// The spotify web api accepts at most 50 track ids per request.
+const TRACK_CHUNK_SIZE: usize = 50;
+
+/// The TrackGaps struct reports the dates missing from a track's daily streams.
+#[derive(Debug, Serialize)]
+pub struct TrackGaps {
+    pub track_id: String,
+    pub dates: Vec<NaiveDate>,
+}
+
+/// The number of past stream days used to predict the refresh time.
+const REFRESH_HISTORY: u64 = 14;
+
+/// Remaining tracks stop being polled this long after the first attempt.
+const REMAINING_TRACKS_DEADLINE: chrono::Duration = chrono::Duration::minutes(13 * 15);
+
+/// The number of runs listed in the run history when no limit is given.
+const RUN_HISTORY_LIMIT: u64 = 30;
+
+/// The review status of quarantined playcounts.
+const QUARANTINE_PENDING: &str = "pending";
+const QUARANTINE_ACCEPTED: &str = "accepted";
+const QUARANTINE_REJECTED: &str = "rejected";
+
+/// Returns the span the work of a daily update stage is recorded in.
+fn stage_span(stage: RunStage) -> Span {
+    info_span!("stage", stage = %stage)
+}
 
 /// DB struct houses primary client interface used to direct application
 pub struct DB {
     pub db: DatabaseConnection,
 }
 
+/// The ArtistUpdate struct reports the artists refreshed by update artist detail, along with the
+/// reason each failed artist was not.
+#[derive(Debug, Default)]
+pub struct ArtistUpdate {
+    pub updated: Vec<String>,
+    pub failed: Vec<(String, String)>,
+}
+
 impl DB {
-    /// Creates and returns a DB struct with an active database connection.
-    pub async fn create() -> Result<Self, DbErr> {
-        dotenv::dotenv().ok();
-        let db_url = env::var("DATABASE_URL").unwrap();
+    /// Creates and returns a DB struct holding the connection pool shared by the whole process,
+    /// configured by the database section of the configuration.
+    pub async fn create() -> Result<Self, AccumulatorError> {
+        DB::connect(&config::current().database).await
+    }
+
+    /// Creates and returns a DB struct holding a connection pool to the database provided.
+    pub async fn connect(config: &DatabaseConfig) -> Result<Self, AccumulatorError> {
+        if config.url.is_empty() {
+            return Err(AccumulatorError::Validation(
+                "DATABASE_URL must be set".to_string(),
+            ));
+        }
+        let mut options = ConnectOptions::new(config.url.clone());
+        options
+            .max_connections(config.max_connections)
+            .connect_timeout(Duration::from_secs(config.connect_timeout))
+            .idle_timeout(Duration::from_secs(config.idle_timeout));
         Ok(Self {
-            db: Database::connect(db_url).await?,
+            db: Database::connect(options).await?,
         })
     }
 
@@ -35,8 +114,11 @@ impl DB {
     }
 
     /// Fetches and returns the album model associated with the given id from the database.
-    pub async fn get_album_by_id(&self, id: &str) -> Result<Option<album::Model>, DbErr> {
-        Album::find_by_id(id).one(&self.db).await
+    pub async fn get_album_by_id(
+        &self,
+        id: &str,
+    ) -> Result<Option<album::Model>, AccumulatorError> {
+        Ok(Album::find_by_id(id).one(&self.db).await?)
     }
 
     /// Fetches returns the augmented HashSet of album ids such that the set only contains IDS
@@ -44,10 +126,11 @@ impl DB {
     async fn get_albums_to_update(
         &self,
         album_ids_fetched: &HashSet<String>,
+        date: NaiveDate,
     ) -> Result<HashSet<String>, DbErr> {
         let mut update_needed = album_ids_fetched.clone();
         let already_completed: HashSet<String> = Album::find()
-            .filter(album::Column::Updated.eq(get_date(0).date_naive()))
+            .filter(album::Column::Updated.eq(stream_day::run_date_for(date)))
             .all(&self.db)
             .await?
             .into_iter()
@@ -58,15 +141,18 @@ impl DB {
     }
 
     /// Fetches and returns the artist model for a given id from the database.
-    pub async fn get_artist_by_id(&self, id: &str) -> Result<Option<artist::Model>, DbErr> {
-        Artist::find_by_id(id).one(&self.db).await
+    pub async fn get_artist_by_id(
+        &self,
+        id: &str,
+    ) -> Result<Option<artist::Model>, AccumulatorError> {
+        Ok(Artist::find_by_id(id).one(&self.db).await?)
     }
 
     /// Fetches and returns the active artist model for a given id from the database.
     pub async fn get_artist_by_id_active(
         &self,
         id: &str,
-    ) -> Result<Option<artist::ActiveModel>, DbErr> {
+    ) -> Result<Option<artist::ActiveModel>, AccumulatorError> {
         match Artist::find_by_id(id).one(&self.db).await? {
             None => Ok(None),
             Some(value) => Ok(Some(value.into_active_model())),
@@ -77,7 +163,7 @@ impl DB {
     pub async fn get_all_artists_standard<P>(
         &self,
         f: fn(Vec<artist::Model>) -> P,
-    ) -> Result<P, DbErr> {
+    ) -> Result<P, AccumulatorError> {
         let artists = Artist::find().all(&self.db).await?;
         Ok(f(artists))
     }
@@ -101,10 +187,12 @@ impl DB {
         &self,
         id: &str,
         playcount: u64,
-    ) -> Result<Option<bool>, Box<dyn Error>> {
+    ) -> Result<Option<bool>, AccumulatorError> {
+        let decisions = &metrics::shared().compare_streams;
         let track = self.get_track_by_id(id).await?;
 
         if track.is_none() {
+            decisions.with_label_values(&["untracked"]).inc();
             return Ok(None);
         }
 
@@ -116,111 +204,333 @@ impl DB {
         let count = ds.len();
 
         if count == 0 {
+            decisions.with_label_values(&["first"]).inc();
             return Ok(Some(true));
         }
 
-        if ds[0].streams as u64 != playcount || (count >= 2 && ds[0].streams - ds[1].streams <= 100)
+        if ds[0].streams as u64 != playcount
+            || (count >= 2 && ds[0].streams - ds[1].streams <= LOW_ACTIVITY_STREAMS)
         {
+            decisions.with_label_values(&["update"]).inc();
             return Ok(Some(true));
         }
+        decisions.with_label_values(&["unchanged"]).inc();
         Ok(Some(false))
     }
 
-    /// Determines whether the given track id is ready to be updated, if true is returned the daily
-    /// update process will begin.
-    pub async fn initial_status_check(id: &str) -> Result<bool, Box<dyn Error>> {
-        let updated_track = TrackUnion::get_union(id).await?;
-        while {
-            let db = DB::create().await?;
-            let value = db.compare_streams(id, updated_track.playcount).await?;
-            value.is_some() && !value.unwrap()
-            //add end of day check
-        } {
-            println!(
-                "Song: {} current stream count {}",
-                updated_track.name, updated_track.playcount
+    /// Waits until spotify's daily playcount refresh is detected, once a quorum of the sentinel
+    /// tracks able to tell have moved true is returned and the daily update process will begin.
+    /// Without any sentinel to compare with the refresh can never be detected, so an error is returned.
+    pub async fn initial_status_check(
+        &self,
+        source: &dyn UnionSource,
+        date: NaiveDate,
+    ) -> Result<bool, AccumulatorError> {
+        let config = &config::current().status_check;
+        let sentinels = self.select_sentinels(config, date).await?;
+        if sentinels.is_empty() {
+            return Err(AccumulatorError::Validation(
+                "no status check sentinels, configure song_ids or track some streams".to_string(),
+            ));
+        }
+        let schedule = self.refresh_schedule().await?;
+        loop {
+            let states = future::join_all(
+                sentinels
+                    .iter()
+                    .map(|id| async move { (id, self.sentinel_state(source, id, date).await) }),
+            )
+            .await;
+            let states = states
+                .into_iter()
+                .map(|(id, state)| match state {
+                    Ok(state) => {
+                        debug!(track_id = %id, ?state, "sentinel checked");
+                        Some(state)
+                    }
+                    Err(error) => {
+                        warn!(track_id = %id, %error, "sentinel check failed");
+                        None
+                    }
+                })
+                .collect::<Vec<Option<SentinelState>>>();
+            let Some((moved, required)) = tally(&states, config.quorum) else {
+                return Err(AccumulatorError::Validation(format!(
+                    "none of the {} status check sentinels has recent daily streams to compare with",
+                    sentinels.len()
+                )));
+            };
+            info!(
+                moved,
+                sentinels = sentinels.len(),
+                quorum = required,
+                "sentinels checked"
             );
-            println!("Not ready for update, waiting 15 min");
-            sleep(Duration::from_secs(900)).await;
+            if moved >= required {
+                self.record_refresh(date, sentinels.len(), moved).await?;
+                return Ok(true);
+            }
+            let wait = schedule.next_wait(stream_day::current().elapsed());
+            info!(
+                wait_min = wait.as_secs() / 60,
+                "refresh not detected, waiting"
+            );
+            if !shutdown::sleep(wait).await {
+                return Err(AccumulatorError::Interrupted);
+            }
         }
-        Ok(true)
     }
 
-    /// Updates the artist detail for all artists within the given slice of artist Ids.
-    pub async fn update_artist_detail(&self, artists: &[String]) -> Result<bool, Box<dyn Error>> {
-        let response = get_artist_detail(format!(
-            "{}/{}?ids={}",
-            "https://api.spotify.com/v1",
-            "artists",
-            artists.join("%2C")
+    /// Builds the refresh schedule from the refresh times observed over the last REFRESH_HISTORY
+    /// stream days, each day's refresh being the earliest time its streams were recorded.
+    async fn refresh_schedule(&self) -> Result<RefreshSchedule, DbErr> {
+        let observed = DailyStreams::find()
+            .select_only()
+            .column(daily_streams::Column::Date)
+            .column_as(daily_streams::Column::Time.min(), "time")
+            .filter(daily_streams::Column::Estimated.eq(false))
+            .group_by(daily_streams::Column::Date)
+            .order_by_desc(daily_streams::Column::Date)
+            .limit(REFRESH_HISTORY)
+            .into_tuple::<(NaiveDate, DateTime<FixedOffset>)>()
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|(_, time)| stream_day::current().elapsed_at(time.with_timezone(&Utc)))
+            .collect::<Vec<chrono::Duration>>();
+        let schedule = RefreshSchedule::new(&observed);
+        if let Some(expected) = schedule.expected() {
+            info!(
+                expected = format!(
+                    "{}h{:02}m",
+                    expected.num_hours(),
+                    expected.num_minutes() % 60
+                ),
+                "refresh expected into the stream day"
+            );
+        }
+        Ok(schedule)
+    }
+
+    /// Returns the configured sentinel tracks along with tracks sampled at random from those
+    /// already tracked which are able to tell the refresh for the stream date provided: tracks with
+    /// streams recorded for the previous two stream dates that gained more than
+    /// LOW_ACTIVITY_STREAMS between them.
+    async fn select_sentinels(
+        &self,
+        config: &StatusCheckConfig,
+        date: NaiveDate,
+    ) -> Result<Vec<String>, DbErr> {
+        let mut sentinels = config.song_ids.clone();
+        let (previous, before) = (Alias::new("previous"), Alias::new("before"));
+        let active = Query::select()
+            .column((previous.clone(), daily_streams::Column::TrackId))
+            .from_as(DailyStreams, previous.clone())
+            .join_as(
+                JoinType::InnerJoin,
+                DailyStreams,
+                before.clone(),
+                Expr::col((before.clone(), daily_streams::Column::TrackId))
+                    .equals((previous.clone(), daily_streams::Column::TrackId)),
+            )
+            .and_where(
+                Expr::col((previous.clone(), daily_streams::Column::Date))
+                    .eq(date.checked_sub_days(Days::new(1))),
+            )
+            .and_where(
+                Expr::col((before.clone(), daily_streams::Column::Date))
+                    .eq(date.checked_sub_days(Days::new(2))),
+            )
+            .and_where(
+                Expr::expr(
+                    Expr::col((previous, daily_streams::Column::Streams))
+                        .sub(Expr::col((before, daily_streams::Column::Streams))),
+                )
+                .gt(LOW_ACTIVITY_STREAMS),
+            )
+            .to_owned();
+        let sampled = Track::find()
+            .filter(track::Column::Id.is_not_in(config.song_ids.clone()))
+            .filter(track::Column::Id.in_subquery(active))
+            .order_by(Expr::cust("random()"), Order::Asc)
+            .limit(config.sample_size)
+            .all(&self.db)
+            .await?;
+        sentinels.extend(sampled.into_iter().map(|track| track.id));
+        Ok(sentinels)
+    }
+
+    /// Scrapes the sentinel track and compares its playcount with the streams recorded for it.
+    async fn sentinel_state(
+        &self,
+        source: &dyn UnionSource,
+        id: &str,
+        date: NaiveDate,
+    ) -> Result<SentinelState, AccumulatorError> {
+        let track = TrackUnion::get_union(source, id).await?;
+        let recorded = DailyStreams::find_by_id((date, id.to_owned()))
+            .one(&self.db)
+            .await?
+            .is_some();
+        let history = DailyStreams::find()
+            .filter(daily_streams::Column::TrackId.eq(id))
+            .filter(daily_streams::Column::Date.lt(date))
+            .order_by_desc(daily_streams::Column::Date)
+            .limit(2)
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|model| (model.date, model.streams))
+            .collect::<Vec<(NaiveDate, i64)>>();
+        Ok(sentinel_state(
+            date,
+            recorded,
+            &history,
+            track.playcount as i64,
         ))
-        .await?;
+    }
 
-        if response.is_empty() {
-            return Ok(false);
-        }
+    /// Records when the refresh for the stream date provided was first detected.
+    async fn record_refresh(
+        &self,
+        date: NaiveDate,
+        sentinels: usize,
+        moved: usize,
+    ) -> Result<(), DbErr> {
+        RefreshDetection::insert(refresh_detection::ActiveModel {
+            date: Set(date),
+            detected_at: Set(stream_day::current().now()),
+            sentinels: Set(sentinels as i32),
+            moved: Set(moved as i32),
+        })
+        .on_conflict(
+            OnConflict::column(refresh_detection::Column::Date)
+                .do_nothing()
+                .to_owned(),
+        )
+        .do_nothing()
+        .exec(&self.db)
+        .await?;
+        Ok(())
+    }
 
-        for artist in response {
-            let images = artist
-                .images
-                .iter()
-                .map(|image| serde_json::to_string(image).unwrap())
-                .collect::<Vec<String>>();
+    /// Updates the artist detail for all artists within the given slice of artist Ids. The spotify
+    /// web api accepts at most 50 ids per request, so the ids are fetched concurrently in chunks and
+    /// the outcome for each artist is reported.
+    pub async fn update_artist_detail(&self, artists: &[String], date: NaiveDate) -> ArtistUpdate {
+        let responses =
+            future::join_all(artists.chunks(ARTIST_CHUNK_SIZE).map(|chunk| async move {
+                let response = get_artist_detail(format!(
+                    "{}/{}?ids={}",
+                    "https://api.spotify.com/v1",
+                    "artists",
+                    chunk.join("%2C")
+                ))
+                .await;
+                (chunk, response)
+            }))
+            .await;
 
-            let active = artist::ActiveModel {
-                name: Set(artist.name.to_owned()),
-                images: Set(images),
-                id: Set(artist.id.to_owned()),
+        let mut report = ArtistUpdate::default();
+        for (chunk, response) in responses {
+            let fetched = match response {
+                Ok(value) => value,
+                Err(error) => {
+                    report.failed.extend(
+                        chunk
+                            .iter()
+                            .map(|id| (id.to_owned(), format!("fetch failed: {}", error))),
+                    );
+                    continue;
+                }
             };
 
-            Artist::insert(active)
-                .on_conflict(
-                    OnConflict::column(artist::Column::Id)
-                        .update_columns([artist::Column::Images, artist::Column::Name])
-                        .to_owned(),
-                )
-                .exec(&self.db)
-                .await?;
+            for id in chunk {
+                let artist = fetched.iter().flatten().find(|artist| &artist.id == id);
+                match artist {
+                    None => report.failed.push((id.to_owned(), "not found".to_string())),
+                    Some(artist) => match self.save_artist(artist, date).await {
+                        Ok(()) => report.updated.push(id.to_owned()),
+                        Err(error) => report.failed.push((id.to_owned(), error.to_string())),
+                    },
+                }
+            }
+        }
+        report
+    }
 
-            FollowerInstance::insert(follower_instance::ActiveModel {
-                artist_id: Set(artist.id.to_owned()),
-                count: Set(artist.followers.total as i32),
-                date: Set(get_date(1).date_naive()),
-            })
+    /// Saves the artist detail and records the artist's current follower count under the stream
+    /// date provided.
+    async fn save_artist(&self, artist: &ArtistAPI, date: NaiveDate) -> Result<(), DbErr> {
+        let images = artist
+            .images
+            .iter()
+            .map(|image| serde_json::to_string(image).unwrap())
+            .collect::<Vec<String>>();
+
+        let active = artist::ActiveModel {
+            name: Set(artist.name.to_owned()),
+            images: Set(images),
+            id: Set(artist.id.to_owned()),
+        };
+
+        Artist::insert(active)
             .on_conflict(
-                OnConflict::columns([
-                    follower_instance::Column::Date,
-                    follower_instance::Column::ArtistId,
-                ])
-                .do_nothing()
-                .to_owned(),
+                OnConflict::column(artist::Column::Id)
+                    .update_columns([artist::Column::Images, artist::Column::Name])
+                    .to_owned(),
             )
-            .do_nothing()
             .exec(&self.db)
             .await?;
-        }
-        Ok(true)
-    }
 
-    /// Creates the artists associated with the given id, once created they will be tracked until deleted.
-    pub async fn create_artist(&self, id: &str) -> Option<artist::Model> {
-        if id == "5K4W6rqBFWDnAN6FQUkS6x" {
-            return None;
-        }
-        let to_create = vec![id.to_string()];
+        FollowerInstance::insert(follower_instance::ActiveModel {
+            artist_id: Set(artist.id.to_owned()),
+            count: Set(artist.followers.total as i32),
+            date: Set(date),
+        })
+        .on_conflict(
+            OnConflict::columns([
+                follower_instance::Column::Date,
+                follower_instance::Column::ArtistId,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .do_nothing()
+        .exec(&self.db)
+        .await?;
+        Ok(())
+    }
 
-        if (self.update_artist_detail(&to_create).await).is_err() {
-            return None;
-        }
+    /// Creates the artists associated with the given id, once created they will be tracked until
+    /// deleted. Blocked artists are refused with a policy error.
+    pub async fn create_artist(&self, id: &str) -> Result<artist::Model, AccumulatorError> {
+        artist_policy::check(self, PolicyList::Blocked, id).await?;
+        let fetched = get_artist_detail(format!(
+            "{}/{}?ids={}",
+            "https://api.spotify.com/v1", "artists", id
+        ))
+        .await
+        .map_err(|error| match error {
+            AccumulatorError::Http {
+                status: Some(400), ..
+            } => AccumulatorError::NotFound(format!("artist {}", id)),
+            error => error,
+        })?;
+        let artist = match fetched.iter().flatten().find(|artist| artist.id == id) {
+            None => return Err(AccumulatorError::NotFound(format!("artist {}", id))),
+            Some(value) => value,
+        };
+        self.save_artist(artist, stream_day::current().stream_date())
+            .await?;
 
-        match self.get_artist_by_id(id).await {
-            Err(_) => None,
-            Ok(value) => value,
-        }
+        self.get_artist_by_id(id)
+            .await?
+            .ok_or_else(|| AccumulatorError::NotFound(format!("artist {}", id)))
     }
 
     /// Deletes the albums associated with only the artist ID supplied.
-    pub async fn delete_associated_albums(&self, id: &str) -> Result<u64, DbErr> {
+    pub async fn delete_associated_albums(&self, id: &str) -> Result<u64, AccumulatorError> {
         let mut albums = Album::find()
             .find_with_related(Artist)
             .filter(
@@ -248,72 +558,581 @@ impl DB {
         Ok(result.rows_affected)
     }
 
-    /// Deletes the artist and all associated information in the database.
-    pub async fn delete_artist(&self, id: &str) -> Result<bool, DbErr> {
-        if id == "06HL4z0CvFAxyc27GXpf02" {
-            return Ok(false);
-        }
+    /// Deletes the artist and all associated information in the database. Protected artists are
+    /// refused with a policy error.
+    pub async fn delete_artist(&self, id: &str) -> Result<(), AccumulatorError> {
+        artist_policy::check(self, PolicyList::Protected, id).await?;
         self.delete_associated_albums(id).await?;
         let result = Artist::delete_by_id(id).exec(&self.db).await?;
-        if result.rows_affected != 0 {
-            return Ok(true);
+        if result.rows_affected == 0 {
+            return Err(AccumulatorError::NotFound(format!("artist {}", id)));
         };
-        Ok(false)
+        Ok(())
+    }
+
+    /// Get policy returns the artists on the blocked and protected lists.
+    pub async fn get_policy(&self) -> Result<PolicyDisplay, AccumulatorError> {
+        Ok(PolicyDisplay::create_policy(self).await?)
+    }
+
+    /// Adds the artist to the policy list provided.
+    pub async fn add_to_policy(
+        &self,
+        list: PolicyList,
+        id: &str,
+        reason: Option<String>,
+    ) -> Result<(), AccumulatorError> {
+        Ok(artist_policy::add(self, list, id, reason).await?)
+    }
+
+    /// Removes the artist from the policy list provided, returning false if it was not listed.
+    pub async fn remove_from_policy(
+        &self,
+        list: PolicyList,
+        id: &str,
+    ) -> Result<bool, AccumulatorError> {
+        artist_policy::remove(self, list, id).await
     }
 
     /// Update artists fetches all artist ids from the data base then calls update artist detail.
-    pub async fn update_artists(&self) -> Result<bool, Box<dyn Error>> {
+    pub async fn update_artists(&self, date: NaiveDate) -> Result<ArtistUpdate, AccumulatorError> {
         let artist_ids = self
             .get_all_artists_standard::<Vec<String>>(|value: Vec<artist::Model>| {
                 value.iter().map(|x| x.id.clone()).collect::<Vec<String>>()
             })
             .await?;
-        self.update_artist_detail(&artist_ids).await
+        Ok(self.update_artist_detail(&artist_ids, date).await)
+    }
+
+    /// Scrapes the artist union of every tracked artist and records its top cities, world rank, and
+    /// monthly listeners. Returns the appears-on album ids of each artist whose union was fetched,
+    /// so get album ids does not scrape the same union again.
+    pub async fn update_artist_overviews(
+        &self,
+        source: &dyn UnionSource,
+        date: NaiveDate,
+    ) -> Result<HashMap<String, Vec<String>>, AccumulatorError> {
+        let artist_ids = self
+            .get_all_artists_standard::<Vec<String>>(|value: Vec<artist::Model>| {
+                value.iter().map(|x| x.id.clone()).collect::<Vec<String>>()
+            })
+            .await?;
+        let appears_on = stream::iter(artist_ids)
+            .map(|id| {
+                let span = info_span!("artist_union", artist_id = %id);
+                async move {
+                    match ArtistUnion::get_union(source, &id).await {
+                        Ok(union) => {
+                            self.update_artist_overview(&union, date).await;
+                            Some((id, union.appears_on()))
+                        }
+                        Err(error) => {
+                            warn!(%error, "artist overview not updated");
+                            None
+                        }
+                    }
+                }
+                .instrument(span)
+            })
+            .buffer_unordered(config::current().update.concurrency)
+            .filter_map(future::ready)
+            .collect::<HashMap<String, Vec<String>>>()
+            .await;
+        Ok(appears_on)
+    }
+
+    /// Validates the playcount scraped for the track against the streams recorded before the given
+    /// date, returning the anomaly found if it should be quarantined.
+    pub(crate) async fn validate_streams(
+        &self,
+        track_id: &str,
+        streams: i64,
+        date: NaiveDate,
+    ) -> Result<Option<Anomaly>, DbErr> {
+        let history = DailyStreams::find()
+            .filter(daily_streams::Column::TrackId.eq(track_id))
+            .filter(daily_streams::Column::Date.lt(date))
+            .order_by_desc(daily_streams::Column::Date)
+            .limit(8)
+            .all(&self.db)
+            .await?
+            .iter()
+            .map(|model| (model.date, model.streams))
+            .collect::<Vec<(NaiveDate, i64)>>();
+        let update = &config::current().update;
+        Ok(check_playcount(
+            &history,
+            date,
+            streams,
+            update.stream_jump_multiple,
+            update.stream_jump_floor,
+        ))
+    }
+
+    /// Holds the playcount flagged by validation until it is accepted or rejected.
+    pub(crate) async fn quarantine_streams(
+        &self,
+        track_id: &str,
+        date: NaiveDate,
+        time: DateTime<FixedOffset>,
+        streams: i64,
+        anomaly: &Anomaly,
+    ) -> Result<(), DbErr> {
+        StreamQuarantine::insert(stream_quarantine::ActiveModel {
+            track_id: Set(track_id.to_owned()),
+            date: Set(date),
+            time: Set(time),
+            streams: Set(streams),
+            reason: Set(anomaly.to_string()),
+            status: Set(QUARANTINE_PENDING.to_string()),
+        })
+        .on_conflict(
+            OnConflict::columns([
+                stream_quarantine::Column::TrackId,
+                stream_quarantine::Column::Date,
+            ])
+            .update_columns([
+                stream_quarantine::Column::Time,
+                stream_quarantine::Column::Streams,
+                stream_quarantine::Column::Reason,
+                stream_quarantine::Column::Status,
+            ])
+            .to_owned(),
+        )
+        .exec(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Fetches every quarantined playcount still waiting for review.
+    pub async fn get_quarantined(&self) -> Result<Vec<stream_quarantine::Model>, AccumulatorError> {
+        Ok(StreamQuarantine::find()
+            .filter(stream_quarantine::Column::Status.eq(QUARANTINE_PENDING))
+            .order_by_asc(stream_quarantine::Column::Date)
+            .all(&self.db)
+            .await?)
+    }
+
+    /// Accepts (recording it as the day's streams) or rejects a pending quarantined playcount, in a
+    /// transaction so the streams are never recorded without the review. Returns false if no
+    /// pending playcount exists for the track and date.
+    pub async fn review_quarantined(
+        &self,
+        track_id: &str,
+        date: NaiveDate,
+        accept: bool,
+    ) -> Result<bool, AccumulatorError> {
+        let txn = self.db.begin().await?;
+        let quarantined = match StreamQuarantine::find_by_id((track_id.to_owned(), date))
+            .lock_exclusive()
+            .one(&txn)
+            .await?
+        {
+            Some(value) if value.status == QUARANTINE_PENDING => value,
+            _ => return Ok(false),
+        };
+
+        if accept {
+            DailyStreams::insert(daily_streams::ActiveModel {
+                date: Set(quarantined.date),
+                track_id: Set(quarantined.track_id.to_owned()),
+                streams: Set(quarantined.streams),
+                time: Set(quarantined.time),
+                estimated: Set(false),
+            })
+            .on_conflict(
+                OnConflict::columns([daily_streams::Column::Date, daily_streams::Column::TrackId])
+                    .update_columns([
+                        daily_streams::Column::Streams,
+                        daily_streams::Column::Time,
+                        daily_streams::Column::Estimated,
+                    ])
+                    .to_owned(),
+            )
+            .exec(&txn)
+            .await?;
+        }
+
+        let mut active = quarantined.into_active_model();
+        active.status = Set(if accept {
+            QUARANTINE_ACCEPTED
+        } else {
+            QUARANTINE_REJECTED
+        }
+        .to_string());
+        active.update(&txn).await?;
+        txn.commit().await?;
+        Ok(true)
+    }
+
+    /// Returns the ids of the tracks missing a date between their first and last recorded daily
+    /// streams, found in a single grouped query.
+    async fn tracks_with_gaps(&self) -> Result<Vec<String>, DbErr> {
+        DailyStreams::find()
+            .select_only()
+            .column(daily_streams::Column::TrackId)
+            .group_by(daily_streams::Column::TrackId)
+            .having(Expr::cust(
+                "max(\"daily_streams\".\"date\") - min(\"daily_streams\".\"date\") + 1 > count(*)",
+            ))
+            .into_tuple::<String>()
+            .all(&self.db)
+            .await
+    }
+
+    /// Scans every track for dates missing between its first and last recorded daily streams. If
+    /// fill is set the missing dates are filled with interpolated rows marked as estimated, filed
+    /// at the start of their stream day as they were never scraped.
+    pub async fn scan_gaps(&self, fill: bool) -> Result<Vec<TrackGaps>, AccumulatorError> {
+        let ids = self.tracks_with_gaps().await?;
+        let streams = DailyStreams::find()
+            .filter(daily_streams::Column::TrackId.is_in(ids))
+            .order_by_asc(daily_streams::Column::TrackId)
+            .order_by_asc(daily_streams::Column::Date)
+            .all(&self.db)
+            .await?;
+        let mut gaps = Vec::new();
+        for streams in streams.chunk_by(|a, b| a.track_id == b.track_id) {
+            let points = streams
+                .iter()
+                .map(|model| (model.date, model.streams))
+                .collect::<Vec<(NaiveDate, i64)>>();
+            let estimated = interpolate_gaps(&points);
+            let track_id = streams[0].track_id.clone();
+            if fill {
+                DailyStreams::insert_many(estimated.iter().map(|(date, streams)| {
+                    daily_streams::ActiveModel {
+                        date: Set(*date),
+                        track_id: Set(track_id.clone()),
+                        time: Set(stream_day::current().filed_at(*date)),
+                        streams: Set(*streams),
+                        estimated: Set(true),
+                    }
+                }))
+                .on_conflict(
+                    OnConflict::columns([
+                        daily_streams::Column::Date,
+                        daily_streams::Column::TrackId,
+                    ])
+                    .do_nothing()
+                    .to_owned(),
+                )
+                .do_nothing()
+                .exec(&self.db)
+                .await?;
+            }
+            gaps.push(TrackGaps {
+                track_id,
+                dates: estimated.into_iter().map(|(date, _)| date).collect(),
+            });
+        }
+        Ok(gaps)
+    }
+
+    /// Fetches the isrc of every track still missing one from the spotify web api and links the
+    /// track to its recording. Tracks are marked as checked once spotify answers for them, with or
+    /// without an isrc, so they are not requested again. Returns the number of tracks linked.
+    pub async fn update_recordings(&self, date: NaiveDate) -> Result<usize, AccumulatorError> {
+        let ids = Track::find()
+            .filter(track::Column::Isrc.is_null())
+            .filter(track::Column::IsrcChecked.is_null())
+            .all(&self.db)
+            .await?
+            .into_iter()
+            .map(|track| track.id)
+            .collect::<Vec<String>>();
+        let responses = future::join_all(
+            ids.chunks(TRACK_CHUNK_SIZE)
+                .map(|chunk| async move { (chunk, get_track_detail(chunk).await) }),
+        )
+        .await;
+
+        let mut linked = 0;
+        for (chunk, response) in responses {
+            let tracks = match response {
+                Ok(value) => value,
+                Err(error) => {
+                    warn!(track_ids = %chunk.join(","), %error, "track detail not fetched");
+                    continue;
+                }
+            };
+            if let Err(error) = Track::update_many()
+                .col_expr(track::Column::IsrcChecked, Expr::value(date))
+                .filter(track::Column::Id.is_in(chunk.iter().cloned()))
+                .exec(&self.db)
+                .await
+            {
+                warn!(track_ids = %chunk.join(","), %error, "isrc lookup not recorded");
+            }
+            for track in tracks.iter().flatten() {
+                let isrc = match track.external_ids.isrc.as_ref() {
+                    None => continue,
+                    Some(value) => value,
+                };
+                match self.save_recording(track, isrc).await {
+                    Ok(()) => linked += 1,
+                    Err(error) => {
+                        warn!(track_id = %track.id, isrc, %error, "recording not saved")
+                    }
+                }
+            }
+        }
+        Ok(linked)
+    }
+
+    /// Creates the recording with the isrc provided (if it does not exist) and links the track to it.
+    async fn save_recording(&self, track: &TrackAPI, isrc: &str) -> Result<(), DbErr> {
+        Recording::insert(recording::ActiveModel {
+            isrc: Set(isrc.to_owned()),
+            name: Set(track.name.to_owned()),
+        })
+        .on_conflict(
+            OnConflict::column(recording::Column::Isrc)
+                .do_nothing()
+                .to_owned(),
+        )
+        .do_nothing()
+        .exec(&self.db)
+        .await?;
+        Track::update_many()
+            .col_expr(track::Column::Isrc, Expr::value(isrc))
+            .filter(track::Column::Id.eq(track.id.as_str()))
+            .exec(&self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Get recording for display returns every release of the recording with its most recent
+    /// streaming information, summed across releases.
+    pub async fn get_recording_for_display(
+        &self,
+        isrc: &str,
+    ) -> Result<Option<RecordingDisplay>, AccumulatorError> {
+        Ok(RecordingDisplay::create_recording(self, isrc).await?)
+    }
+
+    /// Get recording streams returns the daily streams of the recording summed across releases.
+    pub async fn get_recording_streams(
+        &self,
+        isrc: &str,
+    ) -> Result<Vec<StreamPoint>, AccumulatorError> {
+        Ok(StreamPoint::create_points(self, isrc).await?)
+    }
+
+    /// Get runs returns the most recent daily update runs, newest first.
+    pub async fn get_runs(&self, limit: Option<u64>) -> Result<Vec<RunRow>, AccumulatorError> {
+        Ok(RunRow::create_rows(self, limit.unwrap_or(RUN_HISTORY_LIMIT)).await?)
+    }
+
+    /// Get run for display returns the daily update run with its stage durations and failures.
+    pub async fn get_run_for_display(
+        &self,
+        id: i32,
+    ) -> Result<Option<RunDisplay>, AccumulatorError> {
+        Ok(RunDisplay::create_run(self, id).await?)
+    }
+
+    /// Records the monthly listeners, world rank, and top cities found in the scraped artist
+    /// overview for the current date. Each is recorded on its own, a failure is logged without
+    /// skipping the others.
+    async fn update_artist_overview(&self, union: &ArtistUnion, date: NaiveDate) {
+        if let Err(error) = self.update_top_cities(union, date).await {
+            warn!(artist_id = union.id(), %error, "top cities not updated");
+        }
+        if let Err(error) = self.update_world_rank(union, date).await {
+            warn!(artist_id = union.id(), %error, "world rank not updated");
+        }
+        if let Err(error) = self.update_monthly_listeners(union, date).await {
+            warn!(artist_id = union.id(), %error, "monthly listeners not updated");
+        }
+    }
+
+    /// Records the artist's monthly listeners for the stream date provided.
+    async fn update_monthly_listeners(
+        &self,
+        union: &ArtistUnion,
+        date: NaiveDate,
+    ) -> Result<(), DbErr> {
+        let listeners = match union.monthly_listeners().map(i32::try_from) {
+            None => return Ok(()),
+            Some(Ok(value)) => value,
+            Some(Err(error)) => {
+                warn!(artist_id = union.id(), %error, "monthly listeners out of range");
+                return Ok(());
+            }
+        };
+        MonthlyListeners::insert(monthly_listeners::ActiveModel {
+            artist_id: Set(union.id().to_owned()),
+            listeners: Set(listeners),
+            date: Set(date),
+        })
+        .on_conflict(
+            OnConflict::columns([
+                monthly_listeners::Column::Date,
+                monthly_listeners::Column::ArtistId,
+            ])
+            .update_column(monthly_listeners::Column::Listeners)
+            .to_owned(),
+        )
+        .exec(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Records the artist's world rank for the stream date provided.
+    async fn update_world_rank(&self, union: &ArtistUnion, date: NaiveDate) -> Result<(), DbErr> {
+        let rank = match union.world_rank() {
+            None => return Ok(()),
+            Some(value) => value,
+        };
+        WorldRank::insert(world_rank::ActiveModel {
+            artist_id: Set(union.id().to_owned()),
+            date: Set(date),
+            rank: Set(rank as i32),
+        })
+        .on_conflict(
+            OnConflict::columns([world_rank::Column::ArtistId, world_rank::Column::Date])
+                .update_column(world_rank::Column::Rank)
+                .to_owned(),
+        )
+        .exec(&self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Ranks the tracked artists by world rank, or by the rank change over the week or month.
+    pub async fn get_leaderboard(
+        &self,
+        sort: LeaderboardSort,
+    ) -> Result<Vec<LeaderboardRow>, AccumulatorError> {
+        LeaderboardRow::create_rows(self, sort).await
+    }
+
+    /// Records the artist's top listener cities for the stream date provided, replacing any
+    /// snapshot already saved for it. The snapshot is replaced in a transaction so a failed insert
+    /// keeps the previous one. Cities whose listeners do not fit the column are logged and skipped.
+    async fn update_top_cities(&self, union: &ArtistUnion, date: NaiveDate) -> Result<(), DbErr> {
+        let cities = union
+            .top_cities()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, city)| {
+                let listeners = match i32::try_from(city.number_of_listeners) {
+                    Ok(value) => value,
+                    Err(error) => {
+                        warn!(
+                            artist_id = union.id(),
+                            city = %city.city,
+                            %error,
+                            "city listeners out of range"
+                        );
+                        return None;
+                    }
+                };
+                Some(top_city::ActiveModel {
+                    artist_id: Set(union.id().to_owned()),
+                    date: Set(date),
+                    rank: Set(index as i32 + 1),
+                    city: Set(city.city.clone()),
+                    country: Set(city.country.clone()),
+                    region: Set(city.region.clone()),
+                    listeners: Set(listeners),
+                })
+            })
+            .collect::<Vec<top_city::ActiveModel>>();
+        if cities.is_empty() {
+            return Ok(());
+        }
+        let txn = self.db.begin().await?;
+        TopCity::delete_many()
+            .filter(top_city::Column::ArtistId.eq(union.id()))
+            .filter(top_city::Column::Date.eq(date))
+            .exec(&txn)
+            .await?;
+        TopCity::insert_many(cities).exec(&txn).await?;
+        txn.commit().await
+    }
+
+    /// Fetches the top city history recorded for the given artist.
+    pub async fn get_city_history(&self, id: &str) -> Result<Vec<CityDisplay>, AccumulatorError> {
+        Ok(CityDisplay::create_cities(self, id).await?)
+    }
+
+    /// Fetches the monthly listeners and followers recorded for the given artist, oldest first.
+    pub async fn get_listener_history(
+        &self,
+        id: &str,
+    ) -> Result<Vec<ListenerRow>, AccumulatorError> {
+        Ok(ListenerRow::create_rows(self, id).await?)
     }
 
     /// Fetches all artist IDs from two points, all single, compilation, and album ids are fetched
-    /// directly from the spotify web API, while appears_on albums are scraped from the web player.
+    /// directly from the spotify web API, while appears_on albums are taken from the artist unions
+    /// already scraped by update artist overviews. Artists without one are scraped here, recording
+    /// their overview from the same union.
     #[async_recursion]
-    async fn get_album_ids(artist: &HashSet<String>, attempt: u32) -> Option<HashSet<String>> {
+    async fn get_album_ids(
+        &self,
+        source: &dyn UnionSource,
+        artist: &HashSet<String>,
+        appears_on: &HashMap<String, Vec<String>>,
+        date: NaiveDate,
+        attempt: u32,
+    ) -> Option<HashSet<String>> {
         if artist.is_empty() || attempt == 13 {
             return None;
         }
 
-        let mut response_bodies: Vec<Result<Vec<String>, String>> =
-            future::join_all(artist.iter().map(|artist_id| {
-                println!("artist request: {:?}", artist_id);
-                async move { get_artist_albums(artist_id).await }
-            }))
-            .await;
-        response_bodies.extend(
-            future::join_all(artist.iter().map(|artist_id| {
-                println!("artist appears on request: {:?}", artist_id);
-                async move {
-                    get_data::<Vec<String>>(
-                        env::var("ARTIST_END_POINT").unwrap().as_str(),
-                        "artistID",
-                        artist_id,
-                    )
-                    .await
+        let album_bodies = future::join_all(artist.iter().map(|artist_id| {
+            async move { (artist_id, get_artist_albums(artist_id).await) }
+                .instrument(info_span!("artist_albums", artist_id = %artist_id, attempt))
+        }))
+        .await;
+        let appears_on_bodies = future::join_all(artist.iter().map(|artist_id| {
+            async move {
+                if let Some(ids) = appears_on.get(artist_id) {
+                    return (artist_id, Ok(ids.clone()));
                 }
-            }))
-            .await,
-        );
+                let union = ArtistUnion::get_union(source, artist_id).await;
+                if let Ok(union) = union.as_ref() {
+                    self.update_artist_overview(union, date).await;
+                }
+                (artist_id, union.map(|union| union.appears_on()))
+            }
+            .instrument(info_span!("artist_union", artist_id = %artist_id, attempt))
+        }))
+        .await;
 
         let mut ids = Vec::new();
         let mut artist_errors = HashSet::new();
 
-        for response in response_bodies {
+        for (artist_id, response) in album_bodies {
             match response {
                 Ok(value) => ids.push(value),
+                Err(error) if error.is_permanent() => {
+                    warn!(artist_id = %artist_id, %error, "artist albums not fetched");
+                }
                 Err(error) => {
-                    artist_errors.insert(error.clone());
+                    warn!(artist_id = %artist_id, %error, "artist albums failed, will retry");
+                    artist_errors.insert(artist_id.clone());
+                }
+            }
+        }
+        for (artist_id, response) in appears_on_bodies {
+            match response {
+                Ok(value) => ids.push(value),
+                Err(error) => {
+                    warn!(artist_id = %artist_id, %error, "artist appears on failed, will retry");
+                    artist_errors.insert(artist_id.clone());
                 }
             }
         }
 
         let flat_ids = ids.into_iter().flatten().collect::<HashSet<String>>();
-        match DB::get_album_ids(&artist_errors, attempt + 1).await {
+        match self
+            .get_album_ids(source, &artist_errors, appears_on, date, attempt + 1)
+            .await
+        {
             None => Some(flat_ids),
             Some(value) => {
                 let mut to_return = value.clone();
@@ -324,52 +1143,82 @@ impl DB {
     }
 
     /// Update albums 3 handles the final stage of the album update process getting the scraped album
-    /// union from the web player and using it to update/create the album in the database.
-    async fn update_albums_3(albums: HashSet<String>, artists: &HashSet<String>) {
-        let chunk = 50;
-        let response_bodies = stream::iter(albums)
-            .map(|id| async move {
-                match AlbumUnion::get_union(id.as_str()).await {
-                    Ok(value) => value.update(artists).await,
-                    Err(error) => Err(Box::from(format!("Error fetching album {}", error))),
+    /// union from the web player and using it to update/create the album in the database, saving
+    /// each album updated to the run. Once a shutdown is requested no new albums are started.
+    async fn update_albums_3(
+        &self,
+        source: &dyn UnionSource,
+        albums: HashSet<String>,
+        artists: &HashSet<String>,
+        run: &mut UpdateRun,
+    ) -> Result<(), DbErr> {
+        let date = run.date();
+        let chunk = config::current().update.concurrency;
+        let mut response_bodies = stream::iter(albums)
+            .take_while(|_| future::ready(!shutdown::requested()))
+            .map(|id| {
+                let span = info_span!("album", album_id = %id);
+                async move {
+                    let result = match AlbumUnion::get_union(source, id.as_str()).await {
+                        Ok(value) => value.update(self, artists, date).await,
+                        Err(error) => Err(error),
+                    };
+                    (id, result)
                 }
+                .instrument(span)
             })
             .buffer_unordered(chunk);
 
-        response_bodies
-            .for_each(|resp| async {
-                match resp {
-                    Ok(value) => {
-                        println!("Update success {}", value.last_insert_id);
+        while let Some((id, resp)) = response_bodies.next().await {
+            match resp {
+                Ok(value) => {
+                    info!(album_id = %value.last_insert_id, "album updated");
+                    if let Err(error) = run.album_done(self, &id).await {
+                        warn!(album_id = %id, %error, "album not marked as updated");
                     }
-                    Err(e) => {
-                        println!("Update failed {}", e);
+                }
+                Err(e) => {
+                    warn!(album_id = %id, error = %e, "album update failed");
+                    if let Err(error) = run.album_failed(self, &id, &e.to_string()).await {
+                        warn!(album_id = %id, %error, "album failure not recorded");
                     }
                 }
-            })
-            .await;
+            }
+        }
+        Ok(())
     }
 
     /// Update albums 2 handles iterating through available album ids until all have been updated.
     async fn update_albums_2(
         &self,
+        source: &dyn UnionSource,
         album_ids_fetched: &HashSet<String>,
         artists: &HashSet<String>,
+        run: &mut UpdateRun,
     ) -> Result<(), DbErr> {
         let mut albums;
         let mut attempt = 0;
         while {
-            albums = self.get_albums_to_update(album_ids_fetched).await?;
+            albums = self
+                .get_albums_to_update(album_ids_fetched, run.date())
+                .await?;
+            albums.retain(|id| !run.albums_done.contains(id));
             attempt += 1;
-            !albums.is_empty() && attempt <= 13
+            !albums.is_empty() && attempt <= 13 && !shutdown::requested()
         } {
-            DB::update_albums_3(albums, artists).await
+            self.update_albums_3(source, albums, artists, run).await?
         }
         Ok(())
     }
 
-    /// Update albums 1 fetches all album ids associated with tracked artists and calls stage 2
-    pub async fn update_albums_1(&self) -> Result<bool, Box<dyn Error>> {
+    /// Update albums 1 fetches all album ids associated with tracked artists and calls stage 2.
+    /// The album ids are saved to the run, so a resumed run does not fetch them again.
+    pub async fn update_albums_1(
+        &self,
+        source: &dyn UnionSource,
+        appears_on: &HashMap<String, Vec<String>>,
+        run: &mut UpdateRun,
+    ) -> Result<bool, AccumulatorError> {
         let artist_ids = self
             .get_all_artists_standard::<HashSet<String>>(|value: Vec<artist::Model>| {
                 value
@@ -379,28 +1228,48 @@ impl DB {
             })
             .await?;
 
-        match DB::get_album_ids(&artist_ids, 0).await {
-            None => Err(Box::from("Fetching Album IDS failed")),
-            Some(value) => {
-                self.update_albums_2(&value, &artist_ids).await?;
-                Ok(true)
+        if run.album_ids.is_empty() {
+            match self
+                .get_album_ids(source, &artist_ids, appears_on, run.date(), 0)
+                .await
+            {
+                None => return Err(AccumulatorError::NotFound("tracked artists".to_string())),
+                Some(value) => run.set_album_ids(self, value).await?,
             }
         }
+        let album_ids = run.album_ids.clone();
+        self.update_albums_2(source, &album_ids, &artist_ids, run)
+            .await?;
+        Ok(true)
     }
 
-    /// Tracks to update return the album ids of all tracks whose streams have not been updated for the current date.
-    pub async fn tracks_to_update(&self) -> Result<HashSet<String>, DbErr> {
+    /// Tracks to update return the album ids of all tracks whose streams have not been updated
+    /// (or quarantined) for the current date.
+    pub async fn tracks_to_update(
+        &self,
+        date: NaiveDate,
+    ) -> Result<HashSet<String>, AccumulatorError> {
         Ok(Track::find()
             .filter(
-                Condition::any().add(
-                    track::Column::Id.not_in_subquery(
-                        Query::select()
-                            .column(daily_streams::Column::TrackId)
-                            .from(DailyStreams)
-                            .and_where(daily_streams::Column::Date.eq(get_date(1).date_naive()))
-                            .to_owned(),
+                Condition::all()
+                    .add(
+                        track::Column::Id.not_in_subquery(
+                            Query::select()
+                                .column(daily_streams::Column::TrackId)
+                                .from(DailyStreams)
+                                .and_where(daily_streams::Column::Date.eq(date))
+                                .to_owned(),
+                        ),
+                    )
+                    .add(
+                        track::Column::Id.not_in_subquery(
+                            Query::select()
+                                .column(stream_quarantine::Column::TrackId)
+                                .from(StreamQuarantine)
+                                .and_where(stream_quarantine::Column::Date.eq(date))
+                                .to_owned(),
+                        ),
                     ),
-                ),
             )
             .all(&self.db)
             .await?
@@ -411,127 +1280,332 @@ impl DB {
 
     /// Update track by album handles the final stage of the dail update process getting the scraped album
     /// union from the web player and using it to update/create the album in the database.
-    async fn update_tracks_by_album(albums: HashSet<String>) {
-        let chunk = 50;
-        let response_bodies = stream::iter(albums)
-            .map(|id| async move {
-                match AlbumUnion::get_union(id.as_str()).await {
-                    Ok(value) => value.update_track_streams().await,
-                    Err(error) => Err(Box::from(format!("Error fetching album {}", error))),
+    /// Failed albums are recorded against the run.
+    async fn update_tracks_by_album(
+        &self,
+        source: &dyn UnionSource,
+        albums: HashSet<String>,
+        run: &UpdateRun,
+    ) -> Result<(), DbErr> {
+        let date = run.date();
+        let chunk = config::current().update.concurrency;
+        let mut response_bodies = stream::iter(albums)
+            .map(|id| {
+                let span = info_span!("album", album_id = %id);
+                async move {
+                    let result = match AlbumUnion::get_union(source, id.as_str()).await {
+                        Ok(value) => value.update_track_streams(self, date).await,
+                        Err(error) => Err(error),
+                    };
+                    (id, result)
                 }
+                .instrument(span)
             })
             .buffer_unordered(chunk);
 
-        response_bodies
-            .for_each(|resp| async {
-                match resp {
-                    Ok(value) => {
-                        println!("Update success {}", value);
-                    }
-                    Err(e) => {
-                        println!("Update failed {}", e);
+        while let Some((id, resp)) = response_bodies.next().await {
+            match resp {
+                Ok(value) => {
+                    info!(album_id = %id, updated = value, "album streams checked");
+                }
+                Err(e) => {
+                    warn!(album_id = %id, error = %e, "album streams update failed");
+                    if let Err(error) = run.album_failed(self, &id, &e.to_string()).await {
+                        warn!(album_id = %id, %error, "album failure not recorded");
                     }
                 }
-            })
-            .await;
+            }
+        }
+        Ok(())
     }
 
-    /// Update remaining tracks iterates until no tracks remain that have not been updated.
-    pub async fn update_remaining_tracks() -> Result<bool, Box<dyn Error>> {
-        let mut db = DB::create().await?;
-        let mut albums = db.tracks_to_update().await?;
-        let mut attempt = 0;
+    /// Update remaining tracks iterates until no tracks remain that have not been updated, polling
+    /// every few minutes until the deadline. A shutdown requested while waiting ends the stage
+    /// early, leaving the checkpoint to stop the run.
+    pub async fn update_remaining_tracks(
+        &self,
+        source: &dyn UnionSource,
+        run: &UpdateRun,
+    ) -> Result<bool, AccumulatorError> {
+        let mut albums = self.tracks_to_update(run.date()).await?;
+        let start = Utc::now();
         loop {
-            db = DB::create().await?;
-            DB::update_tracks_by_album(albums).await;
-            albums = db.tracks_to_update().await?;
-            if albums.is_empty() && attempt < 13 {
+            self.update_tracks_by_album(source, albums, run).await?;
+            albums = self.tracks_to_update(run.date()).await?;
+            if albums.is_empty()
+                || shutdown::requested()
+                || Utc::now() - start > REMAINING_TRACKS_DEADLINE
+            {
+                break;
+            }
+            let wait = remaining_wait(Utc::now() - start, REMAINING_TRACKS_DEADLINE);
+            info!(
+                wait_min = wait.as_secs() / 60,
+                remaining = albums.len(),
+                "tracks not ready to update, waiting"
+            );
+            if !shutdown::sleep(wait).await {
                 break;
             }
-            attempt += 1;
-            println!("Tracks not ready to update, waiting 15 min");
-            sleep(Duration::from_secs(900)).await;
         }
         Ok(true)
     }
 
     /// Get album for display returns an Album display object containing most recent streaming
     /// information of each track.
-    pub async fn get_album_for_display(id: &str) -> Result<AlbumDisplay, Box<dyn Error>> {
-        let db = DB::create().await?;
+    pub async fn get_album_for_display(&self, id: &str) -> Result<AlbumDisplay, AccumulatorError> {
         let album = Album::find_by_id(id)
             .find_with_related(Track)
-            .all(&db.db)
+            .all(&self.db)
             .await?;
         if album.is_empty() {
-            return Err(Box::from("album does not exist".to_string()));
+            return Err(AccumulatorError::NotFound(format!("album {}", id)));
         }
-        let result = AlbumDisplay::create_album(&db, &album[0]).await?;
+        let result = AlbumDisplay::create_album(self, &album[0]).await?;
         Ok(result)
     }
-    pub async fn get_artist_for_display(id: &str) -> Result<Option<ArtistDisplay>, DbErr> {
-        ArtistDisplay::create_artist(id).await
+
+    /// Get artist for display returns the artist with each of its albums and their most recent
+    /// streaming information.
+    pub async fn get_artist_for_display(
+        &self,
+        id: &str,
+    ) -> Result<Option<ArtistDisplay>, AccumulatorError> {
+        ArtistDisplay::create_artist(self, id).await
     }
 
     /// Daily update guides the flow of the (current) primary component of the application, updating
-    /// the database with the current daily information.
-    pub async fn daily_update() -> Result<chrono::Duration, Box<dyn Error>> {
-        let db = DB::create().await?;
-        DB::initial_status_check(env::var("STATUS_CHECK_SONG_ID")?.as_str())
-            .await
-            .map_err(|error| {
-                println!("Error: {}", error);
-                error
-            })?;
+    /// the database with the current daily information fetched from the given union source. Every
+    /// playcount is filed under the stream date the run started in (the day before the stream day
+    /// in progress, which begins at the configured cutoff hour), even once the update runs past the
+    /// next cutoff. The run is checkpointed after each stage, so a restarted update resumes from the
+    /// stage it stopped in. If a shutdown is requested the update stops between stages.
+    #[instrument(skip_all, fields(run_id = field::Empty))]
+    pub async fn daily_update(
+        &self,
+        source: &dyn UnionSource,
+    ) -> Result<chrono::Duration, AccumulatorError> {
+        let mut run = UpdateRun::resume(self).await?;
+        Span::current().record("run_id", run.id);
+        if run.stage != RunStage::StatusCheck {
+            info!(stage = %run.stage, "resuming update run");
+        }
+        let now = Utc::now();
+        let date = run.date();
+        let mut appears_on = HashMap::new();
 
-        println!("Passed status check");
-        let now = Local::now();
+        if run.stage == RunStage::StatusCheck {
+            self.initial_status_check(source, date)
+                .instrument(stage_span(run.stage))
+                .await
+                .map_err(|error| {
+                    error!(%error, "status check failed");
+                    error
+                })?;
+            info!("passed status check");
+            self.checkpoint(&mut run, RunStage::Artists).await?;
+        }
 
         //update artist detail
-        db.update_artists().await.map_err(|error| {
-            println!("Error updating artists: {}", error);
-            error
-        })?;
-        println!("Artists updated");
+        if run.stage == RunStage::Artists {
+            let span = stage_span(run.stage);
+            let artists = self
+                .update_artists(date)
+                .instrument(span.clone())
+                .await
+                .map_err(|error| {
+                    error!(%error, "artists not updated");
+                    error
+                })?;
+            span.in_scope(|| {
+                for (id, error) in artists.failed.iter() {
+                    warn!(artist_id = %id, %error, "artist not updated");
+                }
+                info!(
+                    succeeded = artists.updated.len(),
+                    failed = artists.failed.len(),
+                    "artists updated"
+                );
+            });
+            match self
+                .update_artist_overviews(source, date)
+                .instrument(stage_span(run.stage))
+                .await
+            {
+                Ok(value) => {
+                    info!(updated = value.len(), "artist overviews updated");
+                    appears_on = value;
+                }
+                Err(error) => error!(%error, "artist overviews not updated"),
+            }
+            self.checkpoint(&mut run, RunStage::Albums).await?;
+        }
 
         //update album detail and initial round of stream updates
-        db.update_albums_1().await.map_err(|error| {
-            println!("Error updating albums: {}", error);
-            error
-        })?;
+        if run.stage == RunStage::Albums {
+            let span = stage_span(run.stage);
+            self.update_albums_1(source, &appears_on, &mut run)
+                .instrument(span)
+                .await
+                .map_err(|error| {
+                    error!(%error, "albums not updated");
+                    error
+                })?;
+            info!("albums updated");
+            self.checkpoint(&mut run, RunStage::Recordings).await?;
+        }
 
-        println!("Albums updated");
+        //link newly stored tracks to their recordings
+        if run.stage == RunStage::Recordings {
+            match self
+                .update_recordings(date)
+                .instrument(stage_span(run.stage))
+                .await
+            {
+                Ok(linked) => info!(linked, "recordings updated"),
+                Err(error) => error!(%error, "recordings not updated"),
+            }
+            self.checkpoint(&mut run, RunStage::RemainingTracks).await?;
+        }
 
-        //update streams until all streams have been updated or it is within 1 hour of the end of the day
-        DB::update_remaining_tracks().await.map_err(|error| {
-            println!("Error updating remaining tracks: {}", error);
-            error
-        })?;
+        //update streams until every track has streams filed under the run's stream date or the
+        //remaining tracks deadline passes
+        if run.stage == RunStage::RemainingTracks {
+            let span = stage_span(run.stage);
+            self.update_remaining_tracks(source, &run)
+                .instrument(span)
+                .await
+                .map_err(|error| {
+                    error!(%error, "remaining tracks not updated");
+                    error
+                })?;
+            self.checkpoint(&mut run, RunStage::Complete).await?;
+        }
+
+        //report days missed by previous updates, they are filled on request
+        match self.tracks_with_gaps().await {
+            Ok(ids) => info!(tracks = ids.len(), "tracks with missing days"),
+            Err(error) => error!(%error, "missing days not scanned"),
+        }
 
-        Ok(Local::now() - now)
+        Ok(Utc::now() - now)
     }
-}
 
-/// Get date returns the date (MM DD YYYY 00:00) of the given day offset.
-pub fn get_date(offset: u64) -> DateTime<Utc> {
-    let date = Local::now().checked_sub_days(Days::new(offset)).unwrap();
-    Utc.with_ymd_and_hms(date.year(), date.month(), date.day(), 0, 0, 0)
-        .unwrap()
+    /// Checkpoint moves the run on to the next stage once the current stage has finished. If a
+    /// shutdown was requested during the stage the run is left at the stage, as it may not have
+    /// completed, and the update stops.
+    async fn checkpoint(
+        &self,
+        run: &mut UpdateRun,
+        next: RunStage,
+    ) -> Result<(), AccumulatorError> {
+        if shutdown::requested() {
+            run.stop(self).await?;
+            info!(stage = %run.stage, "update run stopped");
+            return Err(AccumulatorError::Interrupted);
+        }
+        run.advance(self, next).await?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::entity::{prelude::*, *};
+    use crate::modules::config::{self, StatusCheckConfig};
     use crate::modules::data_base::DB;
-    use sea_orm::EntityTrait;
+    use crate::modules::http_requests::replay;
+    use crate::modules::stream_day;
+    use chrono::NaiveDate;
+    use sea_orm::{DatabaseBackend, EntityTrait, MockDatabase, MockExecResult};
 
     #[tokio::test]
     async fn test_create_db() {
+        config::install_test();
         assert!(DB::create().await.ok().is_some())
     }
 
+    #[tokio::test]
+    async fn test_update_artist_detail_chunks() {
+        let ids = (0..51)
+            .map(|i| format!("4rt1st{:016}", i))
+            .collect::<Vec<String>>();
+        let mock =
+            MockDatabase::new(DatabaseBackend::Postgres).append_exec_results((0..100).map(|_| {
+                MockExecResult {
+                    last_insert_id: 0,
+                    rows_affected: 1,
+                }
+            }));
+        let db = DB {
+            db: mock.into_connection(),
+        };
+
+        let cassette = replay("artist_chunks");
+        let report = cassette
+            .clone()
+            .scope(db.update_artist_detail(&ids, stream_day::current().stream_date()))
+            .await;
+        let requests = cassette
+            .replayed()
+            .into_iter()
+            .filter(|url| url.starts_with("https://api.spotify.com/v1/artists"))
+            .count();
+        assert_eq!(requests, 2);
+        assert_eq!(report.updated, ids[..50]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, ids[50]);
+        assert!(report.failed[0].1.contains("400"));
+
+        let followers = db
+            .db
+            .into_transaction_log()
+            .iter()
+            .map(|transaction| format!("{:?}", transaction))
+            .filter(|statement| statement.contains("follower_instance"))
+            .collect::<Vec<String>>();
+        assert_eq!(followers.len(), 50);
+        assert!(followers
+            .iter()
+            .all(|statement| !statement.contains(&ids[50])));
+    }
+
+    #[tokio::test]
+    async fn test_select_sentinels() {
+        let track = track::Model {
+            id: "s4mpl3d".to_string(),
+            name: "sampled".to_string(),
+            album_id: "4lbum".to_string(),
+            length: 180_000,
+            track_number: Some(1),
+            disc_number: Some(1),
+            isrc: None,
+            isrc_checked: None,
+        };
+        let db = DB {
+            db: MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[track]])
+                .into_connection(),
+        };
+        let config = StatusCheckConfig {
+            song_ids: vec!["c0nf1gur3d".to_string()],
+            sample_size: 5,
+            quorum: None,
+        };
+        let date = NaiveDate::from_ymd_opt(2023, 11, 10).unwrap();
+        let sentinels = db.select_sentinels(&config, date).await.unwrap();
+        assert_eq!(sentinels, ["c0nf1gur3d", "s4mpl3d"]);
+
+        let log = format!("{:?}", db.db.into_transaction_log());
+        assert!(log.contains(r#"\"previous\".\"date\" = $2 AND \"before\".\"date\" = $3"#));
+        assert!(log.contains("ChronoDate(Some(2023-11-09)), ChronoDate(Some(2023-11-08))"));
+        assert!(log.contains(r#"\"previous\".\"streams\" - \"before\".\"streams\" > $4"#));
+        assert!(log.contains("BigInt(Some(100))"));
+    }
+
     #[tokio::test]
     async fn test_all_artists() {
+        config::install_test();
         let db_option = DB::create().await.ok();
         assert!(db_option.is_some());
         let db = db_option.unwrap();
@@ -549,6 +1623,7 @@ mod tests {
     }
     #[tokio::test]
     async fn test_get_artist_by_id() {
+        config::install_test();
         let db_option = DB::create().await.ok();
         assert!(db_option.is_some());
         let db = db_option.unwrap();
@@ -581,6 +1656,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_track_by_id() {
+        config::install_test();
         let db_option = DB::create().await.ok();
         assert!(db_option.is_some());
         let db = db_option.unwrap();
@@ -626,6 +1702,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_get_album_by_id() {
+        config::install_test();
         let db_option = DB::create().await.ok();
         assert!(db_option.is_some());
         let db = db_option.unwrap();
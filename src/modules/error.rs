@@ -0,0 +1,182 @@
+use crate::modules::http_requests::cassette::SendError;
+use crate::modules::http_requests::token::AuthError;
+use crate::modules::http_requests::ApiError;
+use sea_orm::DbErr;
+use std::fmt;
+use tokio::time::Duration;
+
+/// The AccumulatorError enum is returned by every public function in the crate, so callers are able
+/// to tell a missing artist from an unavailable spotify from a failing database.
+#[derive(Debug)]
+pub enum AccumulatorError {
+    /// A request failed on the network or was answered with an unexpected status.
+    Http {
+        status: Option<u16>,
+        message: String,
+    },
+    /// Spotify refused to hand out (or accept) an access token.
+    Auth(String),
+    /// Spotify is limiting the requests sent, retry_after is the wait it asked for.
+    RateLimited { retry_after: Option<Duration> },
+    /// A response or saved union could not be deserialized.
+    Parse(String),
+    /// The artist, album, track, recording, or run requested does not exist.
+    NotFound(String),
+    /// The input or configuration provided is invalid.
+    Validation(String),
+    /// The artist policy forbids the change, the artist is blocked or protected.
+    Policy(String),
+    /// The database query failed.
+    Db(DbErr),
+    /// The daily update stopped early because a shutdown was requested, its progress is saved.
+    Interrupted,
+}
+
+impl AccumulatorError {
+    /// Returns true if retrying the request will not change the outcome.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            AccumulatorError::Http { status, .. } => {
+                status.is_some_and(|status| (400..500).contains(&status))
+            }
+            AccumulatorError::RateLimited { .. }
+            | AccumulatorError::Db(_)
+            | AccumulatorError::Interrupted => false,
+            _ => true,
+        }
+    }
+}
+
+impl AccumulatorError {
+    /// Returns the short name of the variant, used to label metrics.
+    pub fn label(&self) -> &'static str {
+        match self {
+            AccumulatorError::Http { .. } => "http",
+            AccumulatorError::Auth(_) => "auth",
+            AccumulatorError::RateLimited { .. } => "rate_limited",
+            AccumulatorError::Parse(_) => "parse",
+            AccumulatorError::NotFound(_) => "not_found",
+            AccumulatorError::Validation(_) => "validation",
+            AccumulatorError::Policy(_) => "policy",
+            AccumulatorError::Db(_) => "db",
+            AccumulatorError::Interrupted => "interrupted",
+        }
+    }
+}
+
+impl fmt::Display for AccumulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccumulatorError::Http {
+                status: Some(status),
+                message,
+            } => write!(f, "request failed with status {}: {}", status, message),
+            AccumulatorError::Http {
+                status: None,
+                message,
+            } => write!(f, "request failed: {}", message),
+            AccumulatorError::Auth(message) => write!(f, "authorization failed: {}", message),
+            AccumulatorError::RateLimited { retry_after } => {
+                write!(f, "rate limited, retry after {:?}", retry_after)
+            }
+            AccumulatorError::Parse(message) => write!(f, "unparsable: {}", message),
+            AccumulatorError::NotFound(message) => write!(f, "{} not found", message),
+            AccumulatorError::Validation(message) => write!(f, "invalid: {}", message),
+            AccumulatorError::Policy(message) => write!(f, "forbidden by policy: {}", message),
+            AccumulatorError::Db(error) => write!(f, "database error: {}", error),
+            AccumulatorError::Interrupted => write!(f, "update interrupted, progress saved"),
+        }
+    }
+}
+
+impl std::error::Error for AccumulatorError {}
+
+impl From<DbErr> for AccumulatorError {
+    fn from(error: DbErr) -> Self {
+        AccumulatorError::Db(error)
+    }
+}
+
+impl From<AuthError> for AccumulatorError {
+    fn from(error: AuthError) -> Self {
+        match error {
+            AuthError::Request(_) => AccumulatorError::Http {
+                status: None,
+                message: error.to_string(),
+            },
+            AuthError::Rejected { status: 429, .. } => {
+                AccumulatorError::RateLimited { retry_after: None }
+            }
+            AuthError::Rejected { status, .. } if !error.is_permanent() => AccumulatorError::Http {
+                status: Some(status),
+                message: error.to_string(),
+            },
+            error => AccumulatorError::Auth(error.to_string()),
+        }
+    }
+}
+
+impl From<SendError> for AccumulatorError {
+    fn from(error: SendError) -> Self {
+        AccumulatorError::Http {
+            status: None,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<reqwest::Error> for AccumulatorError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_decode() {
+            return AccumulatorError::Parse(error.to_string());
+        }
+        AccumulatorError::Http {
+            status: error.status().map(|status| status.as_u16()),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<ApiError> for AccumulatorError {
+    fn from(error: ApiError) -> Self {
+        match error {
+            ApiError::Auth(error) => error.into(),
+            ApiError::Http(SendError::Http(error)) => error.into(),
+            ApiError::Http(error) => error.into(),
+            ApiError::RateLimited { retry_after } => AccumulatorError::RateLimited { retry_after },
+            ApiError::Status { status, message } => AccumulatorError::Http {
+                status: Some(status),
+                message,
+            },
+        }
+    }
+}
+
+#[test]
+fn test_api_error() {
+    let error = AccumulatorError::from(ApiError::Status {
+        status: 400,
+        message: "invalid id".to_string(),
+    });
+    assert!(matches!(
+        error,
+        AccumulatorError::Http {
+            status: Some(400),
+            ..
+        }
+    ));
+    assert!(error.is_permanent());
+    let error = AccumulatorError::from(ApiError::RateLimited { retry_after: None });
+    assert!(!error.is_permanent());
+    let error = AccumulatorError::from(ApiError::Auth(AuthError::Rejected {
+        status: 503,
+        message: "unavailable".to_string(),
+    }));
+    assert!(!error.is_permanent());
+    let error = AccumulatorError::from(ApiError::Auth(AuthError::Rejected {
+        status: 400,
+        message: "invalid_client".to_string(),
+    }));
+    assert!(matches!(error, AccumulatorError::Auth(_)));
+    assert!(error.is_permanent());
+}
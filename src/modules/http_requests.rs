@@ -1,24 +1,82 @@
+pub(crate) mod cassette;
+pub(crate) mod scheduler;
+pub(crate) mod token;
+
 use crate::modules::album_union::get_id_from_uri;
+use crate::modules::error::AccumulatorError;
+use crate::modules::metrics;
 use crate::modules::track_union::Image;
+use crate::modules::union_source::{UnionKind, UnionSource};
 use async_trait::async_trait;
+use cassette::{client, send, SendError};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::env;
+use std::fmt;
+use token::AuthError;
+use tokio::time::{Duration, Instant};
+use tracing::{debug, instrument, warn};
 
 /// The GetUnion is implemented for objects that are fetched from
-/// an aws endpoint I implemented.
+/// one of the union sources (aws endpoint I implemented, web player, or fixtures).
 #[async_trait]
 pub(crate) trait GetUnion {
-    async fn get_union<'a>(id: &str) -> Result<Self, String>
+    async fn get_union<'a>(source: &dyn UnionSource, id: &str) -> Result<Self, AccumulatorError>
     where
         Self: Sized;
 }
 
-/// The AccessToken struct allows the program to deserialize and utilize the web token fetched
-/// from the spotify web api.
-#[derive(Deserialize, Serialize, Debug)]
-struct AccessToken {
-    access_token: String,
+/// The ApiError enum is returned by requests made to the spotify web api.
+#[derive(Debug)]
+pub(crate) enum ApiError {
+    Auth(AuthError),
+    Http(SendError),
+    RateLimited { retry_after: Option<Duration> },
+    Status { status: u16, message: String },
+}
+
+impl ApiError {
+    /// Returns true if retrying the request will not change the outcome (any 4xx other than 429,
+    /// or a token that can not be obtained).
+    pub(crate) fn is_permanent(&self) -> bool {
+        match self {
+            ApiError::Auth(error) => error.is_permanent(),
+            ApiError::Status { status, .. } => (400..500).contains(status),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Auth(error) => write!(f, "{}", error),
+            ApiError::Http(error) => write!(f, "{}", error),
+            ApiError::RateLimited { retry_after } => {
+                write!(f, "rate limited, retry after {:?}", retry_after)
+            }
+            ApiError::Status { status, message } => write!(f, "status {}: {}", status, message),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<AuthError> for ApiError {
+    fn from(error: AuthError) -> Self {
+        ApiError::Auth(error)
+    }
+}
+
+impl From<SendError> for ApiError {
+    fn from(error: SendError) -> Self {
+        ApiError::Http(error)
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(error: reqwest::Error) -> Self {
+        ApiError::Http(SendError::from(error))
+    }
 }
 
 /// The FollowersAPI struct is used by the ArtistAPI to deserialize follower information
@@ -38,10 +96,32 @@ pub(crate) struct ArtistAPI {
     pub(crate) followers: FollowersAPI,
 }
 
-/// The ArtistsAPI struct holds a vector of Artists returned from the get artists fn implementation.
+/// The ArtistsAPI struct holds a vector of Artists returned from the get artists fn implementation,
+/// spotify returns null in place of any id it does not recognize.
 #[derive(Deserialize, Serialize, Debug)]
 struct ArtistsAPI {
-    artists: Vec<ArtistAPI>,
+    artists: Vec<Option<ArtistAPI>>,
+}
+
+/// The ExternalIdsAPI struct is used by the TrackAPI to deserialize the external ids of a track.
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct ExternalIdsAPI {
+    pub(crate) isrc: Option<String>,
+}
+
+/// The TrackAPI is used to deserialize Track detail obtained from the spotify web API
+#[derive(Deserialize, Serialize, Debug)]
+pub(crate) struct TrackAPI {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) external_ids: ExternalIdsAPI,
+}
+
+/// The TracksAPI struct holds a vector of Tracks returned from the get track detail fn,
+/// spotify returns null in place of any id it does not recognize.
+#[derive(Deserialize, Serialize, Debug)]
+struct TracksAPI {
+    tracks: Vec<Option<TrackAPI>>,
 }
 
 /// The SimpleAlbum struct is used to deserialize the simple album structure returned from the spotify
@@ -60,111 +140,86 @@ struct ArtistAlbumFetch {
     total: u64,
 }
 
-/// The get access token function gets and returns the spotify key needed to use the web api.
-async fn get_spotify_access_token() -> Result<AccessToken, reqwest::Error> {
-    dotenv::dotenv().ok();
-    let client = reqwest::Client::new();
-    let key = env::var("SPOTIFY_KEY").unwrap();
-    client
-        .post("https://accounts.spotify.com/api/token")
-        .header("Content-Type", "application/x-www-form-urlencoded")
-        .body(key.clone())
-        .send()
-        .await?
-        .json::<AccessToken>()
-        .await
-}
-
 /// The get artist detail function gets and returns artist detail for all url query provided.
-pub(crate) async fn get_artist_detail(url: String) -> Result<Vec<ArtistAPI>, reqwest::Error> {
-    let access_token = get_spotify_access_token().await?;
-    Ok(reqwest::Client::new()
-        .get(url)
-        .header(
-            "Authorization",
-            format!("Bearer {}", access_token.access_token),
-        )
-        .send()
+pub(crate) async fn get_artist_detail(
+    url: String,
+) -> Result<Vec<Option<ArtistAPI>>, AccumulatorError> {
+    Ok(scheduler::shared()
+        .get(url.as_str())
         .await?
         .json::<ArtistsAPI>()
         .await?
         .artists)
 }
 
+/// The get track detail function gets and returns track detail (including the isrc) for every
+/// track id provided, at most 50 at a time.
+pub(crate) async fn get_track_detail(
+    ids: &[String],
+) -> Result<Vec<Option<TrackAPI>>, AccumulatorError> {
+    let url = format!("https://api.spotify.com/v1/tracks?ids={}", ids.join("%2C"));
+    debug!(tracks = ids.len(), "fetching track detail");
+    Ok(scheduler::shared()
+        .get(url.as_str())
+        .await?
+        .json::<TracksAPI>()
+        .await?
+        .tracks)
+}
+
 /// the get artist albums function returns all compilation, single, and album ids associated with
 /// an artist.
-pub async fn get_artist_albums(id: &str) -> Result<Vec<String>, String> {
+#[instrument(skip_all, fields(artist_id = id))]
+pub async fn get_artist_albums(id: &str) -> Result<Vec<String>, AccumulatorError> {
     let types = vec!["album", "single", "compilation"];
-    let access_token_object = get_spotify_access_token().await.ok();
-
-    if access_token_object.is_none() {
-        return Err(id.to_string());
-    }
-
-    let access_token = access_token_object.unwrap();
     let mut to_return = Vec::new();
-    let mut next: Option<String>;
 
     for fetch_type in types {
-        match request(format!("https://api.spotify.com/v1/artists/{}/albums?include_groups={}&offset=0&limit=50&locale=en-US,en;q=0.9", id, fetch_type).as_str(), &access_token).await {
-            Ok((ids, next_url)) => {
-                to_return.extend(ids);
-                next = next_url;
-            },
-            Err(_) => return Err(id.to_string()),
-        }
-        while next.is_some() {
-            match request(next.unwrap().as_str(), &access_token).await {
-                Ok((ids, next_url)) => {
-                    to_return.extend(ids);
-                    next = next_url;
-                }
-                Err(_) => return Err(id.to_string()),
-            }
+        let mut next = Some(format!("https://api.spotify.com/v1/artists/{}/albums?include_groups={}&offset=0&limit=50&locale=en-US,en;q=0.9", id, fetch_type));
+        while let Some(url) = next {
+            let (ids, next_url) = request(url.as_str()).await?;
+            to_return.extend(ids);
+            next = next_url;
         }
     }
+    debug!(albums = to_return.len(), "artist albums fetched");
     Ok(to_return)
 }
 
 /// The request function is used by the get artist albums function to make the needed requests
-async fn request(
-    url: &str,
-    access_token: &AccessToken,
-) -> Result<(Vec<String>, Option<String>), ()> {
-    match reqwest::Client::new()
+async fn request(url: &str) -> Result<(Vec<String>, Option<String>), ApiError> {
+    let res = scheduler::shared()
         .get(url)
-        .header(
-            "Authorization",
-            format!("Bearer {}", access_token.access_token),
-        )
-        .send()
-        .await
-    {
-        Err(_) => Err(()),
-        Ok(res) => match res.status() {
-            reqwest::StatusCode::OK => match res.json::<ArtistAlbumFetch>().await {
-                Ok(res) => Ok((
-                    res.items
-                        .iter()
-                        .map(|album| get_id_from_uri(album.uri.as_str()).to_string())
-                        .collect::<Vec<String>>(),
-                    res.next,
-                )),
-                Err(_) => Err(()),
-            },
-            _ => Err(()),
-        },
-    }
+        .await?
+        .json::<ArtistAlbumFetch>()
+        .await?;
+    Ok((
+        res.items
+            .iter()
+            .map(|album| get_id_from_uri(album.uri.as_str()).to_string())
+            .collect::<Vec<String>>(),
+        res.next,
+    ))
 }
 
 /// The get union function is used by the GetUnion trait implementations to get and return an object
-/// of type T.
+/// of type T from the union source provided.
+#[instrument(skip_all, fields(kind = kind.as_str(), id))]
 pub(crate) async fn get_union<T: for<'a> Deserialize<'a>>(
-    url: &str,
+    source: &dyn UnionSource,
+    kind: UnionKind,
     id: &str,
-    key: &str,
-) -> Result<T, String> {
-    get_data::<T>(url, key, id).await
+) -> Result<T, AccumulatorError> {
+    let started = Instant::now();
+    let result = source.fetch(kind, id).await;
+    metrics::shared().observe_union(kind.as_str(), started.elapsed(), &result);
+    let value = result.map_err(|error| {
+        warn!(%error, "union not fetched");
+        error
+    })?;
+    debug!("union fetched");
+    serde_json::from_value::<T>(value)
+        .map_err(|error| AccumulatorError::Parse(format!("{} {}: {}", kind.as_str(), id, error)))
 }
 
 /// The get data function is used to make an HTTP request to the providided url  with the provided
@@ -173,46 +228,134 @@ pub(crate) async fn get_data<T: for<'a> Deserialize<'a>>(
     url: &str,
     key: &str,
     value: &str,
-) -> Result<T, String> {
-    let client = reqwest::Client::new();
+) -> Result<T, AccumulatorError> {
+    let client = client();
     let mut body = HashMap::new();
     body.insert(key, value);
-    match client.get(url).json(&body).send().await {
-        Err(error) => {
-            println!("request failed: {}", error);
-            Err(value.to_owned())
-        }
-        Ok(res) => match res.status() {
-            reqwest::StatusCode::OK => res
-                .json::<T>()
-                .await
-                .map_or_else(|_| Err(value.to_owned()), |value| Ok(value)),
-            _ => {
-                println!("request failed: {:?}", res);
-                Err(value.to_owned())
-            }
-        },
+    let res = send(client.get(url).json(&body)).await?;
+    match res.status() {
+        reqwest::StatusCode::OK => Ok(res.json::<T>().await?),
+        reqwest::StatusCode::NOT_FOUND => Err(AccumulatorError::NotFound(value.to_owned())),
+        status => Err(AccumulatorError::Http {
+            status: Some(status.as_u16()),
+            message: format!("{} {}", key, value),
+        }),
     }
 }
 
+#[cfg(test)]
+use cassette::Cassette;
+
+/// Returns a cassette replaying the interactions recorded in fixtures/cassettes/<name>.json. The
+/// token request body is never recorded so the test configuration's placeholder key will do.
+#[cfg(test)]
+pub(crate) fn replay(name: &str) -> Cassette {
+    crate::modules::config::install_test();
+    Cassette::replay(format!(
+        "{}/fixtures/cassettes/{}.json",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    ))
+    .unwrap()
+}
+
 #[tokio::test]
 async fn test_get_artist_detail() {
-    let url = format!(
-        "{}/{}?ids={}",
-        "https://api.spotify.com/v1", "artists", "06HL4z0CvFAxyc27GXpf02"
-    );
-    assert!(get_artist_detail(url).await.ok().is_some());
-    let url = format!(
-        "{}/{}?ids={}",
-        "https://api.spotify.com/v1", "artists", "z0CvFAxyc27GXpf02"
-    );
-    assert!(get_artist_detail(url).await.ok().is_none());
+    replay("artist_detail")
+        .scope(async {
+            let url = format!(
+                "{}/{}?ids={}",
+                "https://api.spotify.com/v1", "artists", "06HL4z0CvFAxyc27GXpf02"
+            );
+            assert!(get_artist_detail(url).await.ok().is_some());
+            let url = format!(
+                "{}/{}?ids={}",
+                "https://api.spotify.com/v1", "artists", "z0CvFAxyc27GXpf02"
+            );
+            assert!(get_artist_detail(url).await.ok().is_none());
+            let url = format!(
+                "{}/{}?ids={}",
+                "https://api.spotify.com/v1",
+                "artists",
+                "06HL4z0CvFAxyc27GXpf02%2C06HL4z0CvFAxyc27GXpf03"
+            );
+            let artists = get_artist_detail(url).await.unwrap();
+            assert!(artists[0].is_some());
+            assert!(artists[1].is_none());
+        })
+        .await;
+}
+#[tokio::test]
+async fn test_get_track_detail() {
+    replay("track_detail")
+        .scope(async {
+            let tracks = get_track_detail(&[
+                "7Eb9KO7l6Qt8skHG9oRQBD".to_string(),
+                "7Eb9KO7l6Qt8skHG9oRQBX".to_string(),
+            ])
+            .await
+            .unwrap();
+            let track = tracks[0].as_ref().unwrap();
+            assert_eq!(track.external_ids.isrc.as_deref(), Some("USUG12205736"));
+            assert!(tracks[1].is_none());
+        })
+        .await;
 }
 #[tokio::test]
 async fn test_get_album_ids() {
-    let result = get_artist_albums("06HL4z0CvFAxyc27GXpf02").await.ok();
-    assert!(result.is_some());
-    assert!(!result.unwrap().is_empty());
-    let result = get_artist_albums("06HL").await.ok();
-    assert!(result.is_none());
+    replay("artist_albums")
+        .scope(async {
+            let result = get_artist_albums("06HL4z0CvFAxyc27GXpf02").await.ok();
+            assert!(result.is_some());
+            assert!(!result.unwrap().is_empty());
+            let result = get_artist_albums("06HL").await.ok();
+            assert!(result.is_none());
+        })
+        .await;
+}
+#[tokio::test]
+async fn test_get_data_cassette() {
+    let path = std::env::temp_dir().join(format!("stream_accumulator_{}.json", std::process::id()));
+    let server = wiremock::MockServer::start().await;
+    let url = server.uri();
+    wiremock::Mock::given(wiremock::matchers::method("GET"))
+        .respond_with(
+            wiremock::ResponseTemplate::new(200).set_body_json(["2dqn5yOQWdyGwOpOIi9O4x"]),
+        )
+        .mount(&server)
+        .await;
+    let recorded = Cassette::record(&path)
+        .unwrap()
+        .scope(get_data::<Vec<String>>(
+            &url,
+            "artistID",
+            "06HL4z0CvFAxyc27GXpf02",
+        ))
+        .await;
+    drop(server);
+    let replayed = Cassette::replay(&path)
+        .unwrap()
+        .scope(get_data::<Vec<String>>(
+            &url,
+            "artistID",
+            "06HL4z0CvFAxyc27GXpf02",
+        ))
+        .await;
+    std::fs::remove_file(path).unwrap();
+    let recorded = recorded.unwrap();
+    assert_eq!(recorded, vec!["2dqn5yOQWdyGwOpOIi9O4x".to_string()]);
+    assert_eq!(replayed.unwrap(), recorded);
+}
+
+#[test]
+fn test_malformed_cassette() {
+    let path = std::env::temp_dir().join(format!(
+        "stream_accumulator_malformed_{}.json",
+        std::process::id()
+    ));
+    std::fs::write(&path, "[{\"request\": ").unwrap();
+    let result = Cassette::replay(&path);
+    std::fs::remove_file(path).unwrap();
+    assert!(matches!(result, Err(SendError::Cassette(_))));
+    assert!(Cassette::replay(std::env::temp_dir().join("stream_accumulator_missing.json")).is_ok());
 }
@@ -0,0 +1,288 @@
+use crate::modules::config::{self, CassetteConfig};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fmt, fs, io,
+    path::PathBuf,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
+
+/// Requests to these urls carry credentials in their body, which is never written to a cassette
+/// nor used when matching a replayed request.
+const REDACTED_URLS: [&str; 1] = ["https://accounts.spotify.com/api/token"];
+
+/// Requests that have not completed within this long fail, so a hung connection can not hold up
+/// the update or its shutdown.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+tokio::task_local! {
+    static CASSETTE: Cassette;
+}
+
+/// The Mode enum determines whether a cassette saves live interactions or serves saved ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Record,
+    Replay,
+}
+
+/// The RecordedRequest struct holds the part of a request used to match it during replay.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct RecordedRequest {
+    method: String,
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+}
+
+/// The RecordedResponse struct holds everything needed to rebuild a response during replay. JSON
+/// bodies are saved as JSON so cassettes stay readable.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct RecordedResponse {
+    status: u16,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<(String, String)>,
+    body: Value,
+}
+
+/// The Interaction struct pairs a recorded request with the response it received.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+/// The Cassette struct records every request sent through http_requests (and its response) to a
+/// JSON file, or replays a previously recorded file without touching the network.
+#[derive(Clone, Debug)]
+pub(crate) struct Cassette {
+    mode: Mode,
+    path: PathBuf,
+    interactions: Arc<Mutex<Vec<Interaction>>>,
+    replayed: Arc<Mutex<Vec<String>>>,
+}
+
+/// The SendError enum is returned when a request fails either on the network or because the
+/// replayed cassette has no matching interaction.
+#[derive(Debug)]
+pub(crate) enum SendError {
+    Http(reqwest::Error),
+    Cassette(String),
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::Http(error) => write!(f, "{}", error),
+            SendError::Cassette(message) => write!(f, "cassette: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SendError {}
+
+impl From<reqwest::Error> for SendError {
+    fn from(error: reqwest::Error) -> Self {
+        SendError::Http(error)
+    }
+}
+
+impl Cassette {
+    /// Creates a cassette recording to the given file, any interactions already in it are kept.
+    pub(crate) fn record(path: impl Into<PathBuf>) -> Result<Self, SendError> {
+        Self::load(Mode::Record, path.into())
+    }
+
+    /// Creates a cassette replaying the interactions in the given file.
+    pub(crate) fn replay(path: impl Into<PathBuf>) -> Result<Self, SendError> {
+        Self::load(Mode::Replay, path.into())
+    }
+
+    /// Loads the interactions saved in the given file, a missing file holds none. A file that can
+    /// not be read or parsed is an error rather than an empty cassette.
+    fn load(mode: Mode, path: PathBuf) -> Result<Self, SendError> {
+        let interactions = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str::<Vec<Interaction>>(&contents)
+                .map_err(|error| SendError::Cassette(format!("{}: {}", path.display(), error)))?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => {
+                return Err(SendError::Cassette(format!(
+                    "{}: {}",
+                    path.display(),
+                    error
+                )))
+            }
+        };
+        Ok(Self {
+            mode,
+            path,
+            interactions: Arc::new(Mutex::new(interactions)),
+            replayed: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    /// Creates the cassette described by the cassette section of the configuration, none if no
+    /// mode is configured.
+    fn configured(config: &CassetteConfig) -> Result<Option<Self>, SendError> {
+        let (mode, path) = match (config.mode.as_deref(), config.path.as_ref()) {
+            (Some(mode), Some(path)) => (mode, path),
+            _ => return Ok(None),
+        };
+        match mode {
+            "record" => Self::record(path).map(Some),
+            "replay" => Self::replay(path).map(Some),
+            mode => Err(SendError::Cassette(format!(
+                "mode {} must be record or replay",
+                mode
+            ))),
+        }
+    }
+
+    /// Runs the given future with every request it sends going through this cassette.
+    #[cfg(test)]
+    pub(crate) async fn scope<F: std::future::Future>(self, f: F) -> F::Output {
+        CASSETTE.scope(self, f).await
+    }
+
+    /// Returns the url of every request replayed so far, in the order they were sent.
+    #[cfg(test)]
+    pub(crate) fn replayed(&self) -> Vec<String> {
+        self.replayed.lock().unwrap().clone()
+    }
+
+    /// Returns the interaction recorded for the given request, if any.
+    fn find(&self, request: &RecordedRequest) -> Option<Interaction> {
+        self.interactions
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|interaction| {
+                interaction.request.method == request.method
+                    && reqwest::Url::parse(&interaction.request.url)
+                        .is_ok_and(|url| url.as_str() == request.url)
+                    && interaction.request.body == request.body
+            })
+            .cloned()
+    }
+
+    /// Saves the interaction, replacing any previous interaction for the same request.
+    fn save(&self, interaction: Interaction) -> Result<(), SendError> {
+        let mut interactions = self.interactions.lock().unwrap();
+        interactions.retain(|saved| {
+            saved.request.method != interaction.request.method
+                || saved.request.url != interaction.request.url
+                || saved.request.body != interaction.request.body
+        });
+        interactions.push(interaction);
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|error| SendError::Cassette(error.to_string()))?;
+        }
+        fs::write(
+            &self.path,
+            serde_json::to_vec_pretty(&*interactions).unwrap(),
+        )
+        .map_err(|error| SendError::Cassette(error.to_string()))
+    }
+}
+
+/// Returns the cassette in scope for the current task, falling back to the one configured. The
+/// configured cassette is loaded once, a cassette that failed to load fails every request.
+fn current() -> Result<Option<Cassette>, SendError> {
+    static CONFIGURED: OnceLock<Result<Option<Cassette>, String>> = OnceLock::new();
+    if let Ok(cassette) = CASSETTE.try_with(|cassette| cassette.clone()) {
+        return Ok(Some(cassette));
+    }
+    CONFIGURED
+        .get_or_init(|| {
+            Cassette::configured(&config::current().cassette).map_err(|error| match error {
+                SendError::Cassette(message) => message,
+                error => error.to_string(),
+            })
+        })
+        .clone()
+        .map_err(SendError::Cassette)
+}
+
+/// Rebuilds a reqwest response from the recorded response provided.
+fn to_response(recorded: RecordedResponse) -> reqwest::Response {
+    let mut builder = http::Response::builder().status(recorded.status);
+    for (key, value) in recorded.headers.iter() {
+        builder = builder.header(key, value);
+    }
+    let body = match recorded.body {
+        Value::String(text) => text,
+        value => value.to_string(),
+    };
+    reqwest::Response::from(builder.body(body).unwrap())
+}
+
+/// Returns a client whose requests fail once REQUEST_TIMEOUT has passed.
+pub(crate) fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("http client could not be built")
+}
+
+/// The send function sends the request provided, recording or replaying it if a cassette is in
+/// use.
+pub(crate) async fn send(request: reqwest::RequestBuilder) -> Result<reqwest::Response, SendError> {
+    let cassette = match current()? {
+        None => return Ok(request.send().await?),
+        Some(cassette) => cassette,
+    };
+    let (client, request) = request.build_split();
+    let request = request?;
+    let url = request.url().as_str().to_string();
+    let redacted = REDACTED_URLS.contains(&url.as_str());
+    let recorded_request = RecordedRequest {
+        method: request.method().to_string(),
+        body: request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .filter(|_| !redacted)
+            .map(|bytes| String::from_utf8_lossy(bytes).into_owned()),
+        url,
+    };
+
+    if cassette.mode == Mode::Replay {
+        cassette
+            .replayed
+            .lock()
+            .unwrap()
+            .push(recorded_request.url.clone());
+        return match cassette.find(&recorded_request) {
+            Some(interaction) => Ok(to_response(interaction.response)),
+            None => Err(SendError::Cassette(format!(
+                "no interaction recorded for {} {}",
+                recorded_request.method, recorded_request.url
+            ))),
+        };
+    }
+
+    let response = client.execute(request).await?;
+    let status = response.status().as_u16();
+    let headers = response
+        .headers()
+        .iter()
+        .filter(|(key, _)| *key == "content-type" || *key == "retry-after")
+        .map(|(key, value)| {
+            (
+                key.to_string(),
+                value.to_str().unwrap_or_default().to_string(),
+            )
+        })
+        .collect::<Vec<(String, String)>>();
+    let text = response.text().await?;
+    let recorded_response = RecordedResponse {
+        status,
+        headers,
+        body: serde_json::from_str::<Value>(&text).unwrap_or(Value::String(text)),
+    };
+    cassette.save(Interaction {
+        request: recorded_request,
+        response: recorded_response.clone(),
+    })?;
+    Ok(to_response(recorded_response))
+}
@@ -0,0 +1,366 @@
+use crate::modules::config;
+use crate::modules::http_requests::cassette::{client, send, SendError};
+use crate::modules::http_requests::token::{self, TokenManager};
+use crate::modules::http_requests::ApiError;
+use crate::modules::metrics;
+use rand::Rng;
+use std::{collections::VecDeque, sync::OnceLock};
+use tokio::sync::Mutex;
+use tokio::time::{sleep, sleep_until, Duration, Instant};
+use tracing::warn;
+
+/// The RequestScheduler struct sends every spotify web api request. It keeps the number of
+/// requests sent within a rolling window under a global budget, holds every request back until
+/// the Retry-After of a 429 response has passed, and retries transient failures with jittered exponential backoff. A 401 refreshes
+/// the access token and is retried once, other permanent client errors (a bad artist id for
+/// example) are returned immediately.
+pub(crate) struct RequestScheduler {
+    tokens: &'static TokenManager,
+    budget: usize,
+    window: Duration,
+    max_retries: u32,
+    base_backoff: Duration,
+    max_backoff: Duration,
+    sent: Mutex<VecDeque<Instant>>,
+    blocked_until: Mutex<Instant>,
+    client: reqwest::Client,
+}
+
+/// Returns the scheduler shared by the whole process, limited by the configured request budget
+/// (requests per 30 seconds) and retries.
+pub(crate) fn shared() -> &'static RequestScheduler {
+    static SCHEDULER: OnceLock<RequestScheduler> = OnceLock::new();
+    SCHEDULER.get_or_init(|| {
+        let spotify = &config::current().spotify;
+        RequestScheduler::new(
+            token::shared(),
+            spotify.request_budget,
+            Duration::from_secs(30),
+            spotify.max_retries,
+            Duration::from_millis(500),
+            Duration::from_secs(60),
+        )
+    })
+}
+
+impl RequestScheduler {
+    /// Creates a scheduler authorized by the given token manager, allowing budget requests per
+    /// window and max_retries retries per request.
+    pub(crate) fn new(
+        tokens: &'static TokenManager,
+        budget: usize,
+        window: Duration,
+        max_retries: u32,
+        base_backoff: Duration,
+        max_backoff: Duration,
+    ) -> Self {
+        Self {
+            tokens,
+            budget,
+            window,
+            max_retries,
+            base_backoff,
+            max_backoff,
+            sent: Mutex::new(VecDeque::new()),
+            blocked_until: Mutex::new(Instant::now()),
+            client: client(),
+        }
+    }
+
+    /// Waits until any Retry-After received has passed and sending another request keeps the
+    /// scheduler within its budget.
+    async fn acquire(&self) {
+        loop {
+            let blocked_until = *self.blocked_until.lock().await;
+            if blocked_until > Instant::now() {
+                sleep_until(blocked_until).await;
+                continue;
+            }
+            let mut sent = self.sent.lock().await;
+            let now = Instant::now();
+            while sent
+                .front()
+                .is_some_and(|instant| *instant + self.window <= now)
+            {
+                sent.pop_front();
+            }
+            if sent.len() < self.budget {
+                sent.push_back(now);
+                return;
+            }
+            let wait = *sent.front().unwrap() + self.window - now;
+            drop(sent);
+            sleep(wait).await;
+        }
+    }
+
+    /// Holds every request back for the duration provided, unless they are held back longer
+    /// already.
+    async fn block(&self, duration: Duration) {
+        let mut blocked_until = self.blocked_until.lock().await;
+        *blocked_until = (*blocked_until).max(Instant::now() + duration);
+    }
+
+    /// Returns the jittered exponential backoff for the given attempt.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .base_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+        backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+
+    /// Sends an authorized GET request to the url provided, retrying until it succeeds, fails
+    /// permanently, or runs out of retries.
+    pub(crate) async fn get(&self, url: &str) -> Result<reqwest::Response, ApiError> {
+        let mut attempt = 0;
+        let mut reauthorized = false;
+        loop {
+            let access_token = match self.tokens.access_token().await {
+                Ok(access_token) => access_token,
+                Err(error) if error.is_permanent() || attempt >= self.max_retries => {
+                    return Err(error.into())
+                }
+                Err(error) => {
+                    let wait = self.backoff(attempt);
+                    warn!(url, %error, ?wait, attempt, "access token failed, retrying");
+                    metrics::shared().spotify_retries.inc();
+                    attempt += 1;
+                    sleep(wait).await;
+                    continue;
+                }
+            };
+            self.acquire().await;
+            let result = send(
+                self.client
+                    .get(url)
+                    .header("Authorization", format!("Bearer {}", access_token)),
+            )
+            .await;
+
+            let requests = &metrics::shared().spotify_requests;
+            let (error, wait) = match result {
+                Err(SendError::Cassette(message)) => {
+                    return Err(ApiError::Http(SendError::Cassette(message)))
+                }
+                Err(error) => {
+                    requests.with_label_values(&["http_error"]).inc();
+                    (ApiError::Http(error), self.backoff(attempt))
+                }
+                Ok(response) if response.status().is_success() => {
+                    requests.with_label_values(&["ok"]).inc();
+                    return Ok(response);
+                }
+                Ok(response) if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                    requests.with_label_values(&["rate_limited"]).inc();
+                    let retry_after = response
+                        .headers()
+                        .get("retry-after")
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.parse::<u64>().ok())
+                        .map(Duration::from_secs);
+                    if let Some(retry_after) = retry_after {
+                        self.block(retry_after).await;
+                    }
+                    let wait = retry_after.map_or(self.backoff(attempt), |retry_after| {
+                        retry_after.max(self.backoff(attempt))
+                    });
+                    (ApiError::RateLimited { retry_after }, wait)
+                }
+                Ok(response)
+                    if response.status() == reqwest::StatusCode::UNAUTHORIZED && !reauthorized =>
+                {
+                    requests.with_label_values(&["status_error"]).inc();
+                    warn!(url, "access token refused, refreshing it");
+                    self.tokens.invalidate(&access_token).await;
+                    reauthorized = true;
+                    continue;
+                }
+                Ok(response) => {
+                    requests.with_label_values(&["status_error"]).inc();
+                    let error = ApiError::Status {
+                        status: response.status().as_u16(),
+                        message: response.text().await.unwrap_or_default(),
+                    };
+                    if error.is_permanent() {
+                        return Err(error);
+                    }
+                    (error, self.backoff(attempt))
+                }
+            };
+
+            if attempt >= self.max_retries {
+                return Err(error);
+            }
+            warn!(url, %error, ?wait, attempt, "request failed, retrying");
+            metrics::shared().spotify_retries.inc();
+            attempt += 1;
+            sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modules::http_requests::scheduler::RequestScheduler;
+    use crate::modules::http_requests::token::TokenManager;
+    use crate::modules::http_requests::ApiError;
+    use tokio::time::{Duration, Instant};
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn scheduler(budget: usize) -> (MockServer, RequestScheduler) {
+        crate::modules::config::install_test();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "BQCr3cordedSp0tifyW3bAp1T0ken",
+                "token_type": "Bearer",
+                "expires_in": 3600
+            })))
+            .mount(&server)
+            .await;
+        let tokens = Box::leak(Box::new(TokenManager::new(
+            &server.uri(),
+            Some("grant_type=client_credentials".to_string()),
+        )));
+        let scheduler = RequestScheduler::new(
+            tokens,
+            budget,
+            Duration::from_millis(300),
+            3,
+            Duration::from_millis(10),
+            Duration::from_millis(50),
+        );
+        (server, scheduler)
+    }
+
+    async fn requests(server: &MockServer) -> usize {
+        server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| request.method.to_string() == "GET")
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_retry_after() {
+        let (server, scheduler) = scheduler(100).await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        let start = Instant::now();
+        assert!(scheduler.get(&server.uri()).await.is_ok());
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(requests(&server).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_holds_other_requests() {
+        let (server, scheduler) = scheduler(100).await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        let uri = server.uri();
+        let start = Instant::now();
+        let limited = scheduler.get(&uri);
+        let other = async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            let result = scheduler.get(&uri).await;
+            (result, start.elapsed())
+        };
+        let (limited, (other, elapsed)) = tokio::join!(limited, other);
+        assert!(limited.is_ok());
+        assert!(other.is_ok());
+        assert!(elapsed >= Duration::from_secs(1));
+        assert_eq!(requests(&server).await, 3);
+    }
+
+    #[tokio::test]
+    async fn test_permanent_error_fails_fast() {
+        let (server, scheduler) = scheduler(100).await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(400))
+            .mount(&server)
+            .await;
+        let result = scheduler.get(&server.uri()).await;
+        assert!(matches!(result, Err(ApiError::Status { status: 400, .. })));
+        assert_eq!(requests(&server).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_transient_error_retried() {
+        let (server, scheduler) = scheduler(100).await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        let result = scheduler.get(&server.uri()).await;
+        assert!(matches!(result, Err(ApiError::Status { status: 503, .. })));
+        assert_eq!(requests(&server).await, 4);
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized_refreshes_token() {
+        let (server, scheduler) = scheduler(100).await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(401))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        assert!(scheduler.get(&server.uri()).await.is_ok());
+        assert_eq!(requests(&server).await, 2);
+        let tokens = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| request.method.to_string() == "POST")
+            .count();
+        assert_eq!(tokens, 2);
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized_twice_fails() {
+        let (server, scheduler) = scheduler(100).await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(401))
+            .mount(&server)
+            .await;
+        let result = scheduler.get(&server.uri()).await;
+        assert!(matches!(result, Err(ApiError::Status { status: 401, .. })));
+        assert_eq!(requests(&server).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_budget() {
+        let (server, scheduler) = scheduler(2).await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        let start = Instant::now();
+        for _ in 0..3 {
+            scheduler.get(&server.uri()).await.unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+}
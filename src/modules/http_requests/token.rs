@@ -0,0 +1,250 @@
+use crate::modules::config;
+use crate::modules::http_requests::cassette::{client, send, SendError};
+use crate::modules::metrics;
+use serde::Deserialize;
+use std::{fmt, sync::OnceLock};
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+const TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+
+/// Tokens are refreshed once they are within this long of expiring, so a request never goes out
+/// with a token that expires mid-flight.
+const REFRESH_MARGIN: Duration = Duration::from_secs(300);
+
+/// The AccessToken struct allows the program to deserialize and utilize the web token fetched
+/// from the spotify web api.
+#[derive(Deserialize, Debug)]
+struct AccessToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// The CachedToken struct holds an access token along with the instant it expires.
+#[derive(Debug)]
+struct CachedToken {
+    access_token: String,
+    expires_at: Instant,
+}
+
+/// The AuthError enum describes why an access token could not be obtained.
+#[derive(Debug)]
+pub(crate) enum AuthError {
+    MissingKey,
+    Request(SendError),
+    Rejected { status: u16, message: String },
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::MissingKey => write!(f, "SPOTIFY_KEY is not set"),
+            AuthError::Request(error) => write!(f, "token request failed: {}", error),
+            AuthError::Rejected { status, message } => {
+                write!(f, "token request rejected ({}): {}", status, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+impl AuthError {
+    /// Returns true if requesting the token again will not change the outcome, a missing key or
+    /// a 4xx other than 429. Network failures and 5xx responses are transient.
+    pub(crate) fn is_permanent(&self) -> bool {
+        match self {
+            AuthError::MissingKey => true,
+            AuthError::Request(_) => false,
+            AuthError::Rejected { status, .. } => (400..500).contains(status) && *status != 429,
+        }
+    }
+}
+
+/// The TokenManager struct caches the spotify web api access token until shortly before it
+/// expires. Callers wait on the same lock while a refresh is in flight, so only one token request
+/// is ever made at a time.
+pub(crate) struct TokenManager {
+    token_url: String,
+    key: Option<String>,
+    cached: Mutex<Option<CachedToken>>,
+}
+
+/// Returns the token manager shared by the whole process, authorized by the configured spotify key.
+pub(crate) fn shared() -> &'static TokenManager {
+    static TOKEN_MANAGER: OnceLock<TokenManager> = OnceLock::new();
+    TOKEN_MANAGER
+        .get_or_init(|| TokenManager::new(TOKEN_URL, config::current().spotify.key.clone()))
+}
+
+impl TokenManager {
+    /// Creates a token manager requesting tokens from the given url with the client credentials
+    /// form body provided.
+    pub(crate) fn new(token_url: &str, key: Option<String>) -> Self {
+        Self {
+            token_url: token_url.to_owned(),
+            key,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Returns the cached access token, refreshing it first if it is missing or about to expire.
+    pub(crate) async fn access_token(&self) -> Result<String, AuthError> {
+        let mut cached = self.cached.lock().await;
+        if let Some(token) = cached.as_ref() {
+            if token.expires_at > Instant::now() + REFRESH_MARGIN {
+                return Ok(token.access_token.clone());
+            }
+        }
+        let token = self.refresh().await;
+        let outcome = if token.is_ok() { "ok" } else { "failed" };
+        metrics::shared()
+            .token_refreshes
+            .with_label_values(&[outcome])
+            .inc();
+        let token = token?;
+        let access_token = token.access_token.clone();
+        *cached = Some(token);
+        Ok(access_token)
+    }
+
+    /// Drops the cached token if it is the one provided, so the next request refreshes it. Used
+    /// once spotify answers 401 to a token the manager still believed valid.
+    pub(crate) async fn invalidate(&self, access_token: &str) {
+        let mut cached = self.cached.lock().await;
+        if cached
+            .as_ref()
+            .is_some_and(|token| token.access_token == access_token)
+        {
+            *cached = None;
+        }
+    }
+
+    /// Requests a new access token using the client credentials the manager was created with.
+    async fn refresh(&self) -> Result<CachedToken, AuthError> {
+        let key = self.key.clone().ok_or(AuthError::MissingKey)?;
+        let response = send(
+            client()
+                .post(self.token_url.as_str())
+                .header("Content-Type", "application/x-www-form-urlencoded")
+                .body(key),
+        )
+        .await
+        .map_err(AuthError::Request)?;
+
+        if !response.status().is_success() {
+            return Err(AuthError::Rejected {
+                status: response.status().as_u16(),
+                message: response.text().await.unwrap_or_default(),
+            });
+        }
+
+        let token = response
+            .json::<AccessToken>()
+            .await
+            .map_err(|error| AuthError::Request(SendError::from(error)))?;
+        Ok(CachedToken {
+            access_token: token.access_token,
+            expires_at: Instant::now() + Duration::from_secs(token.expires_in),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modules::http_requests::token::{AuthError, TokenManager};
+    use futures::future;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const KEY: &str = "grant_type=client_credentials";
+
+    #[tokio::test]
+    async fn test_access_token_cached() {
+        crate::modules::config::install_test();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "BQCr3cordedSp0tifyW3bAp1T0ken",
+                "token_type": "Bearer",
+                "expires_in": 3600
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let manager = TokenManager::new(&server.uri(), Some(KEY.to_string()));
+        let tokens = future::join_all((0..10).map(|_| manager.access_token())).await;
+        assert!(tokens
+            .iter()
+            .all(|token| token.as_ref().unwrap() == "BQCr3cordedSp0tifyW3bAp1T0ken"));
+    }
+
+    #[tokio::test]
+    async fn test_access_token_refreshed_before_expiry() {
+        crate::modules::config::install_test();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "BQCr3cordedSp0tifyW3bAp1T0ken",
+                "token_type": "Bearer",
+                "expires_in": 60
+            })))
+            .expect(2)
+            .mount(&server)
+            .await;
+        let manager = TokenManager::new(&server.uri(), Some(KEY.to_string()));
+        manager.access_token().await.unwrap();
+        manager.access_token().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_access_token_rejected() {
+        crate::modules::config::install_test();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({
+                "error": "invalid_client",
+                "error_description": "Invalid client"
+            })))
+            .mount(&server)
+            .await;
+        let manager = TokenManager::new(&server.uri(), Some(KEY.to_string()));
+        assert!(matches!(
+            manager.access_token().await,
+            Err(AuthError::Rejected { status: 400, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_access_token_invalidated() {
+        crate::modules::config::install_test();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "BQCr3cordedSp0tifyW3bAp1T0ken",
+                "token_type": "Bearer",
+                "expires_in": 3600
+            })))
+            .expect(2)
+            .mount(&server)
+            .await;
+        let manager = TokenManager::new(&server.uri(), Some(KEY.to_string()));
+        let token = manager.access_token().await.unwrap();
+        manager.invalidate("BQCsupers3dedT0ken").await;
+        manager.access_token().await.unwrap();
+        manager.invalidate(&token).await;
+        manager.access_token().await.unwrap();
+    }
+
+    #[test]
+    fn test_auth_error_permanent() {
+        assert!(AuthError::MissingKey.is_permanent());
+        let rejected = |status| AuthError::Rejected {
+            status,
+            message: String::new(),
+        };
+        assert!(rejected(400).is_permanent());
+        assert!(!rejected(429).is_permanent());
+        assert!(!rejected(503).is_permanent());
+    }
+}
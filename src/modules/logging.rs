@@ -0,0 +1,17 @@
+use crate::modules::config::LoggingConfig;
+use tracing_subscriber::EnvFilter;
+
+/// Installs the global tracing subscriber, writing pretty or JSON lines to stdout filtered by the
+/// configured levels. Span fields (artist, album, and track ids, the run stage, the route) are
+/// included on every event recorded inside the span.
+pub fn init(config: &LoggingConfig) {
+    let filter = EnvFilter::try_new(&config.level).unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    let result = match config.format.as_str() {
+        "json" => builder.json().flatten_event(true).try_init(),
+        _ => builder.try_init(),
+    };
+    if let Err(error) = result {
+        eprintln!("tracing subscriber not installed: {}", error);
+    }
+}
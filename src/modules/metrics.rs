@@ -0,0 +1,205 @@
+use crate::modules::error::AccumulatorError;
+use hyper::header::CONTENT_TYPE as CONTENT_TYPE_HEADER;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+use std::convert::Infallible;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::OnceLock;
+use tokio::time::Duration;
+use tracing::{info, warn};
+
+/// The content type of the text exposition format returned by gather.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// The Metrics struct holds every counter and histogram recorded by the library and the server,
+/// registered in a registry of their own so nothing else ends up on /metrics.
+pub struct Metrics {
+    registry: Registry,
+    /// union_request_duration_seconds{kind, outcome}, the latency of each union fetched.
+    pub union_requests: HistogramVec,
+    /// spotify_requests_total{outcome}, every web api request sent, including retries.
+    pub spotify_requests: IntCounterVec,
+    /// spotify_retries_total, web api requests retried after a transient failure.
+    pub spotify_retries: IntCounter,
+    /// token_refreshes_total{outcome}, access tokens requested from spotify.
+    pub token_refreshes: IntCounterVec,
+    /// compare_streams_total{decision}, the decisions made comparing scraped and stored playcounts.
+    pub compare_streams: IntCounterVec,
+    /// stream_updates_total{result}, playcounts inserted, skipped, or quarantined.
+    pub stream_updates: IntCounterVec,
+    /// http_request_duration_seconds{method, route, status}, the latency of each server request.
+    pub http_requests: HistogramVec,
+}
+
+/// Returns the metrics shared by the whole process.
+pub fn shared() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(|| Metrics::new().unwrap_or_else(|error| panic!("metrics: {}", error)))
+}
+
+impl Metrics {
+    /// Creates and registers every metric.
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new();
+        let metrics = Self {
+            union_requests: HistogramVec::new(
+                HistogramOpts::new(
+                    "union_request_duration_seconds",
+                    "Latency of album, track, and artist unions fetched from the union source.",
+                )
+                .buckets(vec![0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]),
+                &["kind", "outcome"],
+            )?,
+            spotify_requests: IntCounterVec::new(
+                Opts::new(
+                    "spotify_requests_total",
+                    "Spotify web api requests sent, including retries.",
+                ),
+                &["outcome"],
+            )?,
+            spotify_retries: IntCounter::new(
+                "spotify_retries_total",
+                "Spotify web api requests retried after a transient failure.",
+            )?,
+            token_refreshes: IntCounterVec::new(
+                Opts::new("token_refreshes_total", "Spotify access tokens requested."),
+                &["outcome"],
+            )?,
+            compare_streams: IntCounterVec::new(
+                Opts::new(
+                    "compare_streams_total",
+                    "Decisions made comparing scraped playcounts with those stored.",
+                ),
+                &["decision"],
+            )?,
+            stream_updates: IntCounterVec::new(
+                Opts::new(
+                    "stream_updates_total",
+                    "Scraped playcounts inserted, skipped, or quarantined.",
+                ),
+                &["result"],
+            )?,
+            http_requests: HistogramVec::new(
+                HistogramOpts::new(
+                    "http_request_duration_seconds",
+                    "Latency of requests handled by the server.",
+                )
+                .buckets(vec![
+                    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+                ]),
+                &["method", "route", "status"],
+            )?,
+            registry,
+        };
+        metrics
+            .registry
+            .register(Box::new(metrics.union_requests.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.spotify_requests.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.spotify_retries.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.token_refreshes.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.compare_streams.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.stream_updates.clone()))?;
+        metrics
+            .registry
+            .register(Box::new(metrics.http_requests.clone()))?;
+        Ok(metrics)
+    }
+
+    /// Records the latency and outcome of a union fetched.
+    pub(crate) fn observe_union<T>(
+        &self,
+        kind: &str,
+        elapsed: Duration,
+        result: &Result<T, AccumulatorError>,
+    ) {
+        let outcome = match result {
+            Ok(_) => "ok",
+            Err(error) => error.label(),
+        };
+        self.union_requests
+            .with_label_values(&[kind, outcome])
+            .observe(elapsed.as_secs_f64());
+    }
+}
+
+/// Returns every metric in the prometheus text exposition format.
+pub fn gather() -> String {
+    let mut buffer = Vec::new();
+    if let Err(error) = TextEncoder::new().encode(&shared().registry.gather(), &mut buffer) {
+        warn!(%error, "metrics not encoded");
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
+
+/// Serves GET /metrics on the address provided until the process exits, returning the address
+/// bound (so port 0 may be used). Used by the daily update, which has no server of its own; any
+/// other request is answered with 404.
+pub async fn serve(address: &str) -> Result<SocketAddr, AccumulatorError> {
+    let invalid = |error: &dyn std::fmt::Display| {
+        AccumulatorError::Validation(format!("metrics listener {}: {}", address, error))
+    };
+    let listener = TcpListener::bind(address).map_err(|error| invalid(&error))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|error| invalid(&error))?;
+    let server = Server::from_tcp(listener)
+        .map_err(|error| invalid(&error))?
+        .serve(make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(respond))
+        }));
+    let bound = server.local_addr();
+    info!(address = %bound, "serving metrics");
+    tokio::spawn(async move {
+        if let Err(error) = server.await {
+            warn!(%error, "metrics server stopped");
+        }
+    });
+    Ok(bound)
+}
+
+/// Answers GET /metrics with every metric, anything else with 404.
+async fn respond(request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .header(CONTENT_TYPE_HEADER, CONTENT_TYPE)
+            .body(Body::from(gather())),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+    Ok(response.unwrap_or_default())
+}
+
+#[tokio::test]
+async fn test_serve_metrics() {
+    shared()
+        .stream_updates
+        .with_label_values(&["inserted"])
+        .inc();
+    let address = serve("127.0.0.1:0").await.unwrap();
+
+    let response = reqwest::get(format!("http://{}/metrics", address))
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-type"], CONTENT_TYPE);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("stream_updates_total{result=\"inserted\"}"));
+    let status = reqwest::get(format!("http://{}/other", address))
+        .await
+        .unwrap()
+        .status();
+    assert_eq!(status, 404);
+}
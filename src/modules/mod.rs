@@ -1,5 +1,20 @@
 pub(crate) mod album_union;
 pub mod artist_display;
+pub mod artist_policy;
+pub(crate) mod artist_union;
+pub mod config;
 pub mod data_base;
+pub mod error;
 pub(crate) mod http_requests;
+pub mod logging;
+pub mod metrics;
+pub(crate) mod refresh_detection;
+pub(crate) mod refresh_schedule;
+pub mod shutdown;
+pub mod stream_day;
+pub(crate) mod stream_gaps;
+pub(crate) mod stream_validation;
 pub(crate) mod track_union;
+pub mod union_source;
+pub mod update_run;
+pub mod web_player;
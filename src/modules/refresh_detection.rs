@@ -0,0 +1,152 @@
+use chrono::{Days, NaiveDate};
+
+/// Tracks gaining this many streams a day or fewer may not move at the refresh, compare streams
+/// treats them as ready to update and the status check does not rely on them.
+pub(crate) const LOW_ACTIVITY_STREAMS: i64 = 100;
+
+/// The SentinelState enum describes what a sentinel track says about the daily refresh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SentinelState {
+    Moved,
+    Waiting,
+    Unknown,
+}
+
+/// Returns the state of a sentinel for the stream date provided given whether its streams are
+/// already recorded for it, the two most recent streams recorded before it (newest first), and
+/// the playcount just scraped. Only a sentinel with streams recorded for the previous two stream
+/// dates, and gaining more than LOW_ACTIVITY_STREAMS between them, is able to tell.
+pub(crate) fn sentinel_state(
+    date: NaiveDate,
+    recorded: bool,
+    history: &[(NaiveDate, i64)],
+    playcount: i64,
+) -> SentinelState {
+    if recorded {
+        return SentinelState::Moved;
+    }
+    let (previous, before) = match history {
+        [previous, before, ..] => (previous, before),
+        _ => return SentinelState::Unknown,
+    };
+    let current = date.checked_sub_days(Days::new(1)) == Some(previous.0)
+        && date.checked_sub_days(Days::new(2)) == Some(before.0);
+    if !current || previous.1 - before.1 <= LOW_ACTIVITY_STREAMS {
+        return SentinelState::Unknown;
+    }
+    if previous.1 != playcount {
+        SentinelState::Moved
+    } else {
+        SentinelState::Waiting
+    }
+}
+
+/// Returns the number of sentinels that must move for the refresh to be declared, a simple
+/// majority unless configured otherwise.
+pub(crate) fn quorum(sentinels: usize, configured: Option<usize>) -> usize {
+    configured
+        .unwrap_or(sentinels / 2 + 1)
+        .clamp(1, sentinels.max(1))
+}
+
+/// Returns the sentinels moved and the quorum required of those able to tell, sentinels whose
+/// state is unknown are left out and those whose check failed count as not moved. None is
+/// returned when no sentinel is able to tell.
+pub(crate) fn tally(
+    states: &[Option<SentinelState>],
+    configured: Option<usize>,
+) -> Option<(usize, usize)> {
+    let known = states
+        .iter()
+        .filter(|state| **state != Some(SentinelState::Unknown))
+        .count();
+    if known == 0 {
+        return None;
+    }
+    let moved = states
+        .iter()
+        .filter(|state| **state == Some(SentinelState::Moved))
+        .count();
+    Some((moved, quorum(known, configured)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modules::refresh_detection::{quorum, sentinel_state, tally, SentinelState};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_sentinel_state() {
+        let date = |day: u32| NaiveDate::from_ymd_opt(2023, 11, day).unwrap();
+        let history = [(date(9), 5_000), (date(8), 4_000)];
+        assert_eq!(
+            sentinel_state(date(10), true, &history, 5_000),
+            SentinelState::Moved
+        );
+        assert_eq!(
+            sentinel_state(date(10), false, &history, 6_000),
+            SentinelState::Moved
+        );
+        assert_eq!(
+            sentinel_state(date(10), false, &history, 5_000),
+            SentinelState::Waiting
+        );
+        assert_eq!(
+            sentinel_state(date(10), false, &history[..1], 6_000),
+            SentinelState::Unknown
+        );
+    }
+
+    #[test]
+    fn test_sentinel_state_low_activity() {
+        // A track whose playcount barely moves would keep waiting after the refresh.
+        let date = |day: u32| NaiveDate::from_ymd_opt(2023, 11, day).unwrap();
+        let history = [(date(9), 4_050), (date(8), 4_000)];
+        assert_eq!(
+            sentinel_state(date(10), false, &history, 4_050),
+            SentinelState::Unknown
+        );
+    }
+
+    #[test]
+    fn test_sentinel_state_stale() {
+        // A track last recorded weeks ago would move before the refresh.
+        let date = |day: u32| NaiveDate::from_ymd_opt(2023, 11, day).unwrap();
+        let history = [(date(2), 5_000), (date(1), 4_000)];
+        assert_eq!(
+            sentinel_state(date(10), false, &history, 9_000),
+            SentinelState::Unknown
+        );
+        let history = [(date(9), 5_000), (date(1), 4_000)];
+        assert_eq!(
+            sentinel_state(date(10), false, &history, 9_000),
+            SentinelState::Unknown
+        );
+    }
+
+    #[test]
+    fn test_quorum() {
+        assert_eq!(quorum(1, None), 1);
+        assert_eq!(quorum(5, None), 3);
+        assert_eq!(quorum(6, None), 4);
+        assert_eq!(quorum(5, Some(2)), 2);
+        assert_eq!(quorum(3, Some(7)), 3);
+        assert_eq!(quorum(3, Some(0)), 1);
+    }
+
+    #[test]
+    fn test_tally() {
+        use SentinelState::*;
+        assert_eq!(tally(&[], None), None);
+        assert_eq!(tally(&[Some(Unknown), Some(Unknown)], None), None);
+        assert_eq!(
+            tally(&[Some(Moved), Some(Unknown), Some(Unknown)], None),
+            Some((1, 1))
+        );
+        assert_eq!(
+            tally(&[Some(Moved), Some(Waiting), None, Some(Unknown)], None),
+            Some((1, 2))
+        );
+        assert_eq!(tally(&[None, None], Some(1)), Some((0, 1)));
+    }
+}
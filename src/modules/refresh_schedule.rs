@@ -0,0 +1,88 @@
+use chrono::Duration;
+
+/// Polling starts this long before the expected refresh.
+const LEAD: Duration = Duration::minutes(30);
+
+/// Polling stays frequent until this long after the expected refresh.
+const WINDOW: Duration = Duration::minutes(90);
+
+/// The wait between polls near the expected refresh.
+const FAST_POLL: Duration = Duration::minutes(3);
+
+/// The wait between polls when the refresh time is unknown or long overdue.
+const SLOW_POLL: Duration = Duration::minutes(15);
+
+/// The RefreshSchedule struct predicts when spotify's daily playcount refresh happens, measured
+/// from the start of the stream day, from the refresh times previously observed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct RefreshSchedule {
+    expected: Option<Duration>,
+}
+
+impl RefreshSchedule {
+    /// Creates a schedule expecting the refresh at the median of the observed refresh times.
+    pub(crate) fn new(observed: &[Duration]) -> Self {
+        let mut observed = observed.to_vec();
+        observed.sort();
+        Self {
+            expected: observed.get(observed.len() / 2).copied(),
+        }
+    }
+
+    /// Returns the expected refresh time, if any refresh has been observed.
+    pub(crate) fn expected(&self) -> Option<Duration> {
+        self.expected
+    }
+
+    /// Returns how long to wait before polling again, given how far into the stream day it is.
+    /// Before the refresh window it sleeps until the window opens, within it it polls often.
+    pub(crate) fn next_wait(&self, elapsed: Duration) -> std::time::Duration {
+        let wait = match self.expected {
+            None => SLOW_POLL,
+            Some(expected) if elapsed < expected - LEAD => expected - LEAD - elapsed,
+            Some(expected) if elapsed < expected + WINDOW => FAST_POLL,
+            Some(_) => SLOW_POLL,
+        };
+        wait.to_std().unwrap_or(FAST_POLL.to_std().unwrap())
+    }
+}
+
+/// Returns how long to wait before polling again for tracks not yet refreshed, given how long
+/// since the polling started and the deadline it stops at. The refresh has already been detected,
+/// however early, so the schedule no longer applies and it polls often without passing the deadline.
+pub(crate) fn remaining_wait(elapsed: Duration, deadline: Duration) -> std::time::Duration {
+    FAST_POLL
+        .min(deadline - elapsed)
+        .to_std()
+        .unwrap_or(std::time::Duration::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modules::refresh_schedule::{remaining_wait, RefreshSchedule};
+    use chrono::Duration;
+
+    #[test]
+    fn test_next_wait() {
+        let minutes = |value: i64| Duration::minutes(value);
+        let unknown = RefreshSchedule::new(&[]);
+        assert_eq!(unknown.expected(), None);
+        assert_eq!(unknown.next_wait(minutes(0)).as_secs(), 15 * 60);
+
+        let schedule = RefreshSchedule::new(&[minutes(400), minutes(300), minutes(310)]);
+        assert_eq!(schedule.expected(), Some(minutes(310)));
+        assert_eq!(schedule.next_wait(minutes(60)).as_secs(), 220 * 60);
+        assert_eq!(schedule.next_wait(minutes(290)).as_secs(), 3 * 60);
+        assert_eq!(schedule.next_wait(minutes(600)).as_secs(), 15 * 60);
+    }
+
+    #[test]
+    fn test_remaining_wait_early_refresh() {
+        let minutes = |value: i64| Duration::minutes(value);
+        let schedule = RefreshSchedule::new(&[minutes(310)]);
+        assert_eq!(schedule.next_wait(minutes(60)).as_secs(), 220 * 60);
+        assert_eq!(remaining_wait(minutes(0), minutes(195)).as_secs(), 3 * 60);
+        assert_eq!(remaining_wait(minutes(194), minutes(195)).as_secs(), 60);
+        assert_eq!(remaining_wait(minutes(200), minutes(195)).as_secs(), 0);
+    }
+}
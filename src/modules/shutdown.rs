@@ -0,0 +1,59 @@
+use std::sync::OnceLock;
+use tokio::sync::watch;
+use tokio::time::Duration;
+use tracing::{info, warn};
+
+/// Returns the channel flipped to true once a shutdown is requested.
+fn channel() -> &'static watch::Sender<bool> {
+    static SHUTDOWN: OnceLock<watch::Sender<bool>> = OnceLock::new();
+    SHUTDOWN.get_or_init(|| watch::channel(false).0)
+}
+
+/// Listens for SIGINT and SIGTERM. Once either is received no new work is started, in-flight
+/// albums are finished, and the update saves its progress before exiting. A second signal exits
+/// immediately, without waiting for the work in flight.
+pub fn listen() {
+    tokio::spawn(async {
+        signal().await;
+        info!("shutdown requested, finishing in-flight work");
+        request();
+        signal().await;
+        warn!("second shutdown signal, exiting without saving progress");
+        std::process::exit(130);
+    });
+}
+
+/// Waits for the next SIGINT or SIGTERM.
+async fn signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+                .expect("SIGTERM handler could not be installed");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => (),
+            _ = terminate.recv() => (),
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.ok();
+}
+
+/// Requests a shutdown.
+pub(crate) fn request() {
+    channel().send_replace(true);
+}
+
+/// Returns true once a shutdown has been requested.
+pub(crate) fn requested() -> bool {
+    *channel().borrow()
+}
+
+/// Sleeps for the duration provided, returning false early if a shutdown is requested.
+pub(crate) async fn sleep(duration: Duration) -> bool {
+    let mut receiver = channel().subscribe();
+    tokio::select! {
+        _ = tokio::time::sleep(duration) => true,
+        _ = receiver.wait_for(|requested| *requested) => false,
+    }
+}
@@ -0,0 +1,147 @@
+use crate::modules::config;
+use crate::modules::error::AccumulatorError;
+use chrono::{DateTime, Days, Duration, FixedOffset, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::sync::OnceLock;
+
+/// The StreamDay struct defines the day playcounts are filed under, independent of the timezone
+/// of the machine running the update. A stream day begins at the cutoff hour in the configured
+/// timezone; the playcount scraped during a stream day is filed under the previous day, the last
+/// day fully counted by spotify.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamDay {
+    timezone: Tz,
+    cutoff_hour: u32,
+}
+
+/// Returns the stream day during which playcounts filed under the stream date provided are
+/// scraped, the day after it.
+pub fn run_date_for(stream_date: NaiveDate) -> NaiveDate {
+    stream_date.checked_add_days(Days::new(1)).unwrap()
+}
+
+/// Returns the stream day shared by the whole process, set by the configured timezone (an IANA
+/// timezone, UTC by default) and cutoff hour (0 by default).
+pub fn current() -> &'static StreamDay {
+    static STREAM_DAY: OnceLock<StreamDay> = OnceLock::new();
+    STREAM_DAY.get_or_init(|| {
+        config::current()
+            .stream_day()
+            .unwrap_or_else(|error| panic!("{}", error))
+    })
+}
+
+impl StreamDay {
+    /// Creates a stream day beginning at the cutoff hour (0-23) in the given timezone.
+    pub fn new(timezone: Tz, cutoff_hour: u32) -> Result<Self, AccumulatorError> {
+        if cutoff_hour > 23 {
+            return Err(AccumulatorError::Validation(format!(
+                "stream day cutoff hour {} is not 0-23",
+                cutoff_hour
+            )));
+        }
+        Ok(Self {
+            timezone,
+            cutoff_hour,
+        })
+    }
+
+    /// Parses the timezone name and cutoff hour provided.
+    pub fn parse(timezone: &str, cutoff_hour: &str) -> Result<Self, AccumulatorError> {
+        let timezone = timezone.parse::<Tz>().map_err(|_| {
+            AccumulatorError::Validation(format!("unknown stream day timezone {}", timezone))
+        })?;
+        let cutoff_hour = cutoff_hour.parse::<u32>().map_err(|_| {
+            AccumulatorError::Validation(format!("stream day cutoff hour {}", cutoff_hour))
+        })?;
+        Self::new(timezone, cutoff_hour)
+    }
+
+    /// Returns the stream day in progress at the instant provided.
+    pub fn run_date_at(&self, now: DateTime<Utc>) -> NaiveDate {
+        (now.with_timezone(&self.timezone) - Duration::hours(self.cutoff_hour as i64)).date_naive()
+    }
+
+    /// Returns the date playcounts scraped at the instant provided are filed under.
+    pub fn stream_date_at(&self, now: DateTime<Utc>) -> NaiveDate {
+        self.run_date_at(now)
+            .checked_sub_days(Days::new(1))
+            .unwrap()
+    }
+
+    /// Returns the instant the stream day filing playcounts under the date provided begins, the
+    /// time given to estimated playcounts as none were scraped.
+    pub fn filed_at(&self, date: NaiveDate) -> DateTime<FixedOffset> {
+        let start = run_date_for(date)
+            .and_hms_opt(self.cutoff_hour, 0, 0)
+            .unwrap();
+        self.timezone
+            .from_local_datetime(&start)
+            .earliest()
+            .unwrap_or_else(|| self.timezone.from_utc_datetime(&start))
+            .fixed_offset()
+    }
+
+    /// Returns how far the instant provided is into the stream day it falls in.
+    pub fn elapsed_at(&self, instant: DateTime<Utc>) -> Duration {
+        let local =
+            instant.with_timezone(&self.timezone) - Duration::hours(self.cutoff_hour as i64);
+        local.time().signed_duration_since(NaiveTime::MIN)
+    }
+
+    /// Returns how far into the current stream day it is.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed_at(Utc::now())
+    }
+
+    /// Returns the stream day in progress.
+    pub fn run_date(&self) -> NaiveDate {
+        self.run_date_at(Utc::now())
+    }
+
+    /// Returns the date playcounts scraped now are filed under.
+    pub fn stream_date(&self) -> NaiveDate {
+        self.stream_date_at(Utc::now())
+    }
+
+    /// Returns the current instant in the stream day's timezone.
+    pub fn now(&self) -> DateTime<FixedOffset> {
+        Utc::now().with_timezone(&self.timezone).fixed_offset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modules::stream_day::StreamDay;
+    use chrono::{Duration, NaiveDate, TimeZone, Utc};
+
+    #[test]
+    fn test_stream_day() {
+        let date = |day: u32| NaiveDate::from_ymd_opt(2023, 11, day).unwrap();
+        let instant = Utc.with_ymd_and_hms(2023, 11, 10, 6, 30, 0).unwrap();
+
+        let utc = StreamDay::parse("UTC", "0").unwrap();
+        assert_eq!(utc.run_date_at(instant), date(10));
+        assert_eq!(utc.stream_date_at(instant), date(9));
+
+        let pacific = StreamDay::parse("America/Los_Angeles", "0").unwrap();
+        assert_eq!(pacific.run_date_at(instant), date(9));
+
+        let cutoff = StreamDay::parse("UTC", "8").unwrap();
+        assert_eq!(cutoff.run_date_at(instant), date(9));
+        assert_eq!(cutoff.stream_date_at(instant), date(8));
+
+        assert_eq!(cutoff.elapsed_at(instant), Duration::minutes(22 * 60 + 30));
+        assert_eq!(
+            cutoff.filed_at(date(8)),
+            Utc.with_ymd_and_hms(2023, 11, 9, 8, 0, 0).unwrap()
+        );
+        assert_eq!(
+            cutoff.stream_date_at(cutoff.filed_at(date(8)).into()),
+            date(8)
+        );
+
+        assert!(StreamDay::parse("Mars/Olympus_Mons", "0").is_err());
+        assert!(StreamDay::parse("UTC", "24").is_err());
+    }
+}
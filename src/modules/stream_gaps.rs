@@ -0,0 +1,38 @@
+use chrono::{Days, NaiveDate};
+
+/// The interpolate gaps function returns a linearly interpolated playcount for every date missing
+/// between the recorded (date, streams) points provided, which must be ordered by date.
+pub(crate) fn interpolate_gaps(points: &[(NaiveDate, i64)]) -> Vec<(NaiveDate, i64)> {
+    let mut estimated = Vec::new();
+    for pair in points.windows(2) {
+        let ((start, start_streams), (end, end_streams)) = (pair[0], pair[1]);
+        let days = (end - start).num_days();
+        for offset in 1..days {
+            let date = start.checked_add_days(Days::new(offset as u64)).unwrap();
+            let streams = start_streams + (end_streams - start_streams) * offset / days;
+            estimated.push((date, streams));
+        }
+    }
+    estimated
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modules::stream_gaps::interpolate_gaps;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_interpolate_gaps() {
+        let date = |day: u32| NaiveDate::from_ymd_opt(2023, 11, day).unwrap();
+        assert!(interpolate_gaps(&[(date(1), 100), (date(2), 200)]).is_empty());
+        assert_eq!(
+            interpolate_gaps(&[
+                (date(1), 100),
+                (date(2), 200),
+                (date(5), 500),
+                (date(7), 500)
+            ]),
+            vec![(date(3), 300), (date(4), 400), (date(6), 500)]
+        );
+    }
+}
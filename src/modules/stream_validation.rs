@@ -0,0 +1,135 @@
+use chrono::NaiveDate;
+use std::fmt;
+
+/// The Anomaly enum describes why a scraped playcount was quarantined instead of recorded.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Anomaly {
+    Decrease { previous: i64, current: i64 },
+    Zero { previous: i64 },
+    Jump { increase: i64, average: f64 },
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Anomaly::Decrease { previous, current } => {
+                write!(f, "decrease from {} to {}", previous, current)
+            }
+            Anomaly::Zero { previous } => write!(f, "zero after {}", previous),
+            Anomaly::Jump { increase, average } => write!(
+                f,
+                "jump of {} against trailing average of {:.0}",
+                increase, average
+            ),
+        }
+    }
+}
+
+/// Checks the playcount for the date provided against the streams previously recorded for the
+/// track (most recent first), returning the anomaly found if it should not be recorded. Increases
+/// are compared per day, so days missing between rows do not inflate them. Jumps are only flagged
+/// once the daily increase is at least jump_floor streams, so quiet tracks picking up a handful of
+/// plays are not quarantined.
+pub(crate) fn check_playcount(
+    history: &[(NaiveDate, i64)],
+    date: NaiveDate,
+    playcount: i64,
+    jump_multiple: f64,
+    jump_floor: u64,
+) -> Option<Anomaly> {
+    let (previous_date, previous) = *history.first()?;
+    if playcount == 0 && previous > 0 {
+        return Some(Anomaly::Zero { previous });
+    }
+    if playcount < previous {
+        return Some(Anomaly::Decrease {
+            previous,
+            current: playcount,
+        });
+    }
+    let (oldest_date, oldest) = *history.last()?;
+    if oldest_date >= previous_date {
+        return None;
+    }
+    let increase = playcount - previous;
+    let daily_increase = increase as f64 / days_between(previous_date, date);
+    let average = (previous - oldest) as f64 / days_between(oldest_date, previous_date);
+    if daily_increase >= jump_floor as f64 && daily_increase > average * jump_multiple {
+        return Some(Anomaly::Jump { increase, average });
+    }
+    None
+}
+
+/// Returns the number of days from the first date to the second, at least one.
+fn days_between(from: NaiveDate, to: NaiveDate) -> f64 {
+    (to - from).num_days().max(1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modules::stream_validation::{check_playcount, Anomaly};
+    use chrono::NaiveDate;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 11, day).unwrap()
+    }
+
+    #[test]
+    fn test_check_playcount() {
+        let history = [
+            (date(9), 1_300_000),
+            (date(8), 1_200_000),
+            (date(7), 1_100_000),
+        ];
+        let check = |history: &[(NaiveDate, i64)], playcount, multiple| {
+            check_playcount(history, date(10), playcount, multiple, 10_000)
+        };
+        assert_eq!(check(&history, 1_400_000, 10.0), None);
+        assert_eq!(check(&[], 0, 10.0), None);
+        assert_eq!(
+            check(&history, 0, 10.0),
+            Some(Anomaly::Zero {
+                previous: 1_300_000
+            })
+        );
+        assert_eq!(
+            check(&history, 1_250_000, 10.0),
+            Some(Anomaly::Decrease {
+                previous: 1_300_000,
+                current: 1_250_000
+            })
+        );
+        assert!(matches!(
+            check(&history, 3_400_000, 10.0),
+            Some(Anomaly::Jump {
+                increase: 2_100_000,
+                ..
+            })
+        ));
+        assert_eq!(check(&history, 3_400_000, 25.0), None);
+        assert_eq!(check(&[(date(9), 100), (date(8), 100)], 5_000, 10.0), None);
+        assert!(check_playcount(
+            &[(date(9), 100), (date(8), 100)],
+            date(10),
+            5_000,
+            10.0,
+            1_000
+        )
+        .is_some());
+    }
+
+    #[test]
+    fn test_check_playcount_missed_days() {
+        // 100,000 a day with the 8th missed is not an average of 200,000 a day.
+        let history = [(date(9), 1_300_000), (date(7), 1_100_000)];
+        assert!(matches!(
+            check_playcount(&history, date(10), 2_500_000, 10.0, 10_000),
+            Some(Anomaly::Jump { average, .. }) if average == 100_000.0
+        ));
+        // Two days of normal growth since the previous row is not a jump.
+        assert_eq!(
+            check_playcount(&history, date(11), 1_500_000, 1.5, 10_000),
+            None
+        );
+    }
+}
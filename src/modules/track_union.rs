@@ -1,10 +1,13 @@
 use crate::modules::album_union::Duration;
+use crate::modules::error::AccumulatorError;
 use crate::modules::http_requests;
 use crate::modules::http_requests::GetUnion;
+#[cfg(test)]
+use crate::modules::union_source::FixtureSource;
+use crate::modules::union_source::{UnionKind, UnionSource};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use serde_aux::field_attributes::deserialize_number_from_string;
-use std::env;
 
 /// Used throughout the library to deserialize standard image JSON objects.
 #[derive(Deserialize, Serialize, Debug)]
@@ -39,21 +42,20 @@ pub struct TrackUnion {
     sharing_info: SharingInfo,
 }
 
-/// The Track Union Implementation of the GetUnion Trait fetches the track from the aws endpoint I created
-/// which intercepts the network traffic from spotify's web player in order ot obtain the playcount
-/// for track in the album (as well as other track details).
+/// The Track Union Implementation of the GetUnion Trait fetches the track from the union source
+/// provided (the aws endpoint I created, spotify's web player, or fixtures) in order ot obtain the
+/// playcount for track in the album (as well as other track details).
 #[async_trait]
 impl GetUnion for TrackUnion {
-    async fn get_union<'a>(id: &str) -> Result<Self, String> {
-        dotenv::dotenv().ok();
-        let key = env::var("TRACK_END_POINT").unwrap();
-        http_requests::get_union::<Self>(key.as_str(), id, "trackID").await
+    async fn get_union<'a>(source: &dyn UnionSource, id: &str) -> Result<Self, AccumulatorError> {
+        http_requests::get_union::<Self>(source, UnionKind::Track, id).await
     }
 }
 
 #[tokio::test]
 async fn test_get_track_union() {
-    let union = TrackUnion::get_union("7Eb9KO7l6Qt8skHG9oRQBD")
+    let source = FixtureSource::new(format!("{}/fixtures/unions", env!("CARGO_MANIFEST_DIR")));
+    let union = TrackUnion::get_union(&source, "7Eb9KO7l6Qt8skHG9oRQBD")
         .await
         .ok()
         .unwrap();
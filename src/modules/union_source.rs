@@ -0,0 +1,269 @@
+use crate::modules::config::SourceConfig;
+use crate::modules::error::AccumulatorError;
+use crate::modules::http_requests::get_data;
+use crate::modules::web_player::WebPlayer;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tracing::{debug, warn};
+
+/// The UnionKind enum names the three unions a UnionSource is able to fetch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnionKind {
+    Album,
+    Track,
+    Artist,
+}
+
+impl UnionKind {
+    /// Returns the name used for the union kind in fixture directories.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnionKind::Album => "album",
+            UnionKind::Track => "track",
+            UnionKind::Artist => "artist",
+        }
+    }
+}
+
+/// The UnionSource trait is implemented by each backend able to supply the raw album, track, and
+/// artist union JSON consumed by the GetUnion implementations.
+#[async_trait]
+pub trait UnionSource: Send + Sync {
+    async fn fetch(&self, kind: UnionKind, id: &str) -> Result<Value, AccumulatorError>;
+
+    /// Fetches the page of album tracks starting at offset, returned as the album union's tracks
+    /// object. Sources unable to page through album tracks return a validation error.
+    async fn fetch_album_tracks(
+        &self,
+        id: &str,
+        _offset: usize,
+    ) -> Result<Value, AccumulatorError> {
+        Err(AccumulatorError::Validation(format!(
+            "album {} tracks can not be paged by this source",
+            id
+        )))
+    }
+}
+
+/// The LambdaSource fetches unions from the aws endpoints I implemented.
+pub struct LambdaSource {
+    album_end_point: String,
+    track_end_point: String,
+    artist_end_point: String,
+}
+
+impl LambdaSource {
+    /// Creates a lambda source pointed at the given album, track, and artist endpoints.
+    pub fn new(album_end_point: &str, track_end_point: &str, artist_end_point: &str) -> Self {
+        Self {
+            album_end_point: album_end_point.to_owned(),
+            track_end_point: track_end_point.to_owned(),
+            artist_end_point: artist_end_point.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+impl UnionSource for LambdaSource {
+    async fn fetch(&self, kind: UnionKind, id: &str) -> Result<Value, AccumulatorError> {
+        match kind {
+            UnionKind::Album => get_data::<Value>(&self.album_end_point, "albumID", id).await,
+            UnionKind::Track => get_data::<Value>(&self.track_end_point, "trackID", id).await,
+            UnionKind::Artist => {
+                // the artist lambda only returns the appears on album ids, so they are reshaped
+                // into the artist union structure returned by the web player.
+                let ids = get_data::<Vec<String>>(&self.artist_end_point, "artistID", id).await?;
+                Ok(json!({
+                    "uri": format!("spotify:artist:{}", id),
+                    "relatedContent": {
+                        "appearsOn": {
+                            "items": [{
+                                "releases": {
+                                    "items": ids
+                                        .iter()
+                                        .map(|id| json!({ "uri": format!("spotify:album:{}", id) }))
+                                        .collect::<Vec<Value>>(),
+                                }
+                            }]
+                        }
+                    }
+                }))
+            }
+        }
+    }
+}
+
+/// The FixtureSource reads unions previously saved to a local directory, laid out as
+/// `<dir>/<kind>/<id>.json`, allowing the daily update to run without network access.
+pub struct FixtureSource {
+    dir: PathBuf,
+}
+
+impl FixtureSource {
+    /// Creates a fixture source reading from the given directory.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+/// Returns the path of the fixture file for the given union within the directory provided.
+fn fixture_path(dir: &Path, kind: UnionKind, id: &str) -> PathBuf {
+    dir.join(kind.as_str()).join(format!("{}.json", id))
+}
+
+#[async_trait]
+impl UnionSource for FixtureSource {
+    async fn fetch(&self, kind: UnionKind, id: &str) -> Result<Value, AccumulatorError> {
+        let path = fixture_path(&self.dir, kind, id);
+        let contents = fs::read_to_string(&path).map_err(|error| {
+            debug!(path = %path.display(), %error, "fixture unavailable");
+            AccumulatorError::NotFound(format!("{} {}", kind.as_str(), id))
+        })?;
+        serde_json::from_str::<Value>(&contents).map_err(|error| {
+            AccumulatorError::Parse(format!("fixture {}: {}", path.display(), error))
+        })
+    }
+}
+
+/// The RecordingSource proxies another source, saving each union it returns in the layout read
+/// by the FixtureSource.
+pub struct RecordingSource {
+    inner: Box<dyn UnionSource>,
+    dir: PathBuf,
+}
+
+impl RecordingSource {
+    /// Creates a recording source saving the unions fetched by inner to the given directory.
+    pub fn new(inner: Box<dyn UnionSource>, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            dir: dir.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl UnionSource for RecordingSource {
+    async fn fetch(&self, kind: UnionKind, id: &str) -> Result<Value, AccumulatorError> {
+        let value = self.inner.fetch(kind, id).await?;
+        let path = fixture_path(&self.dir, kind, id);
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, serde_json::to_vec_pretty(&value).unwrap()));
+        if let Err(error) = written {
+            warn!(path = %path.display(), %error, "union not recorded");
+        }
+        Ok(value)
+    }
+
+    async fn fetch_album_tracks(&self, id: &str, offset: usize) -> Result<Value, AccumulatorError> {
+        self.inner.fetch_album_tracks(id, offset).await
+    }
+}
+
+/// Creates the union source named by the source configuration (lambda, scraper, or fixtures).
+/// When unnamed the lambda is used if the album end point is set, otherwise the web player is
+/// scraped directly. If a record directory is set every union fetched is also recorded to it.
+pub fn from_config(config: &SourceConfig) -> Result<Box<dyn UnionSource>, AccumulatorError> {
+    let required = |value: &Option<String>, key: &str| {
+        value
+            .clone()
+            .ok_or_else(|| AccumulatorError::Validation(format!("{} must be set", key)))
+    };
+
+    let source: Box<dyn UnionSource> = match config.kind() {
+        "lambda" => Box::new(LambdaSource::new(
+            required(&config.album_end_point, "ALBUM_END_POINT")?.as_str(),
+            required(&config.track_end_point, "TRACK_END_POINT")?.as_str(),
+            required(&config.artist_end_point, "ARTIST_END_POINT")?.as_str(),
+        )),
+        "scraper" => Box::new(WebPlayer::create(config)),
+        "fixtures" => Box::new(FixtureSource::new(required(
+            &config.fixture_dir,
+            "UNION_FIXTURE_DIR",
+        )?)),
+        name => {
+            return Err(AccumulatorError::Validation(format!(
+                "unknown UNION_SOURCE {}",
+                name
+            )))
+        }
+    };
+
+    match &config.record_dir {
+        Some(dir) => Ok(Box::new(RecordingSource::new(source, dir))),
+        None => Ok(source),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modules::error::AccumulatorError;
+    use crate::modules::union_source::{
+        FixtureSource, LambdaSource, RecordingSource, UnionKind, UnionSource,
+    };
+    use std::{env, fs};
+    use wiremock::matchers::{body_json, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn fixtures() -> FixtureSource {
+        FixtureSource::new(format!("{}/fixtures/unions", env!("CARGO_MANIFEST_DIR")))
+    }
+
+    #[tokio::test]
+    async fn test_fixture_source() {
+        let source = fixtures();
+        let album = source
+            .fetch(UnionKind::Album, "51hV7ASoVjOVuIhbib79We")
+            .await
+            .unwrap();
+        assert_eq!(album["name"], "Anti-Hero (feat. Bleachers)");
+        assert!(matches!(
+            source.fetch(UnionKind::Track, "7Eb9KO7l6Qt8").await,
+            Err(AccumulatorError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_recording_source() {
+        let dir = env::temp_dir().join(format!("stream_accumulator_{}", std::process::id()));
+        let source = RecordingSource::new(Box::new(fixtures()), &dir);
+        source
+            .fetch(UnionKind::Track, "7Eb9KO7l6Qt8skHG9oRQBD")
+            .await
+            .unwrap();
+        let replayed = FixtureSource::new(&dir)
+            .fetch(UnionKind::Track, "7Eb9KO7l6Qt8skHG9oRQBD")
+            .await
+            .unwrap();
+        assert_eq!(replayed["name"], "Anti-Hero");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_lambda_source_artist() {
+        crate::modules::config::install_test();
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(body_json(
+                serde_json::json!({ "artistID": "06HL4z0CvFAxyc27GXpf02" }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(["2dqn5yOQWdyGwOpOIi9O4x"]))
+            .mount(&server)
+            .await;
+        let source = LambdaSource::new(&server.uri(), &server.uri(), &server.uri());
+        let artist = source
+            .fetch(UnionKind::Artist, "06HL4z0CvFAxyc27GXpf02")
+            .await
+            .unwrap();
+        assert_eq!(
+            artist["relatedContent"]["appearsOn"]["items"][0]["releases"]["items"][0]["uri"],
+            "spotify:album:2dqn5yOQWdyGwOpOIi9O4x"
+        );
+    }
+}
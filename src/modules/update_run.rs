@@ -0,0 +1,459 @@
+use crate::entity::{daily_streams, update_run, update_run_error, update_run_stage};
+use crate::modules::data_base::DB;
+use crate::modules::stream_day;
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, DbErr, EntityTrait, IntoActiveModel,
+    ModelTrait, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set, Unchanged,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, fmt};
+
+/// The RunStage enum names the stages of the daily update, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RunStage {
+    StatusCheck,
+    Artists,
+    Albums,
+    Recordings,
+    RemainingTracks,
+    Complete,
+}
+
+impl RunStage {
+    /// Returns the name the stage is saved under.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunStage::StatusCheck => "status_check",
+            RunStage::Artists => "artists",
+            RunStage::Albums => "albums",
+            RunStage::Recordings => "recordings",
+            RunStage::RemainingTracks => "remaining_tracks",
+            RunStage::Complete => "complete",
+        }
+    }
+
+    /// Parses a saved stage name, unknown names restart the run from the status check.
+    pub fn parse(stage: &str) -> Self {
+        match stage {
+            "artists" => RunStage::Artists,
+            "albums" => RunStage::Albums,
+            "recordings" => RunStage::Recordings,
+            "remaining_tracks" => RunStage::RemainingTracks,
+            "complete" => RunStage::Complete,
+            _ => RunStage::StatusCheck,
+        }
+    }
+}
+
+impl fmt::Display for RunStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// The UpdateRun struct holds the checkpoint of a daily update: the stage in progress, the album
+/// ids fetched, and the albums already updated. Each album is saved as it is updated, so a resumed
+/// run skips every album finished before it stopped.
+pub struct UpdateRun {
+    pub id: i32,
+    pub stage: RunStage,
+    pub(crate) album_ids: HashSet<String>,
+    pub(crate) albums_done: HashSet<String>,
+    date: NaiveDate,
+    started_at: DateTime<FixedOffset>,
+    stage_started: DateTime<FixedOffset>,
+    tracks_updated: i32,
+}
+
+impl UpdateRun {
+    /// Resumes the unfinished run for the current stream day, or starts a new one.
+    pub(crate) async fn resume(db: &DB) -> Result<Self, DbErr> {
+        let date = stream_day::current().stream_date();
+        let unfinished = update_run::Entity::find()
+            .filter(update_run::Column::Date.eq(date))
+            .filter(update_run::Column::Stage.ne(RunStage::Complete.as_str()))
+            .order_by_desc(update_run::Column::Id)
+            .one(&db.db)
+            .await?;
+        let model = match unfinished {
+            Some(model) => model,
+            None => {
+                update_run::ActiveModel {
+                    date: Set(date),
+                    stage: Set(RunStage::StatusCheck.as_str().to_string()),
+                    album_ids: Set(Vec::new()),
+                    albums_done: Set(Vec::new()),
+                    started_at: Set(stream_day::current().now()),
+                    updated_at: Set(stream_day::current().now()),
+                    tracks_updated: Set(0),
+                    ..Default::default()
+                }
+                .insert(&db.db)
+                .await?
+            }
+        };
+        Ok(Self {
+            id: model.id,
+            stage: RunStage::parse(&model.stage),
+            album_ids: model.album_ids.into_iter().collect(),
+            albums_done: model.albums_done.into_iter().collect(),
+            date: model.date,
+            started_at: model.started_at,
+            stage_started: stream_day::current().now(),
+            tracks_updated: model.tracks_updated,
+        })
+    }
+
+    /// Returns the stream date the run files playcounts under, fixed when the run started so a run
+    /// crossing the cutoff does not write under two dates.
+    pub(crate) fn date(&self) -> NaiveDate {
+        self.date
+    }
+
+    /// Saves the run's checkpoint, the albums done are saved as each is updated.
+    pub(crate) async fn save(&self, db: &DB) -> Result<(), DbErr> {
+        let mut active = update_run::ActiveModel {
+            id: Unchanged(self.id),
+            stage: Set(self.stage.as_str().to_string()),
+            album_ids: Set(self.album_ids.iter().cloned().collect()),
+            updated_at: Set(stream_day::current().now()),
+            tracks_updated: Set(self.tracks_updated),
+            ..Default::default()
+        };
+        if self.stage == RunStage::Complete {
+            active.ended_at = Set(Some(stream_day::current().now()));
+        }
+        active.update(&db.db).await?;
+        Ok(())
+    }
+
+    /// Moves the run on to the stage provided and saves it.
+    pub(crate) async fn advance(&mut self, db: &DB, stage: RunStage) -> Result<(), DbErr> {
+        self.record_stage(db).await?;
+        self.stage = stage;
+        self.save(db).await
+    }
+
+    /// Saves the run without moving it on, as the update is stopping part way through the stage.
+    pub(crate) async fn stop(&mut self, db: &DB) -> Result<(), DbErr> {
+        self.record_stage(db).await?;
+        self.save(db).await
+    }
+
+    /// Adds the time spent in the current stage to its duration, stages resumed by a later process
+    /// accumulate the time spent by each, and counts the tracks the run has updated so far.
+    async fn record_stage(&mut self, db: &DB) -> Result<(), DbErr> {
+        let now = stream_day::current().now();
+        let elapsed = (now - self.stage_started).num_milliseconds();
+        let existing = update_run_stage::Entity::find_by_id((self.id, self.stage.to_string()))
+            .one(&db.db)
+            .await?;
+        match existing {
+            Some(model) => {
+                let duration_ms = model.duration_ms + elapsed;
+                let mut active = model.into_active_model();
+                active.ended_at = Set(now);
+                active.duration_ms = Set(duration_ms);
+                active.update(&db.db).await?;
+            }
+            None => {
+                update_run_stage::ActiveModel {
+                    run_id: Set(self.id),
+                    stage: Set(self.stage.to_string()),
+                    started_at: Set(self.stage_started),
+                    ended_at: Set(now),
+                    duration_ms: Set(elapsed),
+                }
+                .insert(&db.db)
+                .await?;
+            }
+        }
+        self.stage_started = now;
+        self.tracks_updated = daily_streams::Entity::find()
+            .filter(daily_streams::Column::Date.eq(self.date))
+            .filter(daily_streams::Column::Estimated.eq(false))
+            .filter(daily_streams::Column::Time.gte(self.started_at))
+            .count(&db.db)
+            .await? as i32;
+        Ok(())
+    }
+
+    /// Saves the album ids fetched for the run.
+    pub(crate) async fn set_album_ids(
+        &mut self,
+        db: &DB,
+        album_ids: HashSet<String>,
+    ) -> Result<(), DbErr> {
+        self.album_ids = album_ids;
+        self.save(db).await
+    }
+
+    /// Marks the album as updated, appending it to the albums done saved for the run without
+    /// rewriting the rest of the checkpoint.
+    pub(crate) async fn album_done(&mut self, db: &DB, id: &str) -> Result<(), DbErr> {
+        update_run::Entity::update_many()
+            .col_expr(
+                update_run::Column::AlbumsDone,
+                Expr::cust_with_values("array_append(\"albums_done\", $1)", [id]),
+            )
+            .col_expr(
+                update_run::Column::UpdatedAt,
+                Expr::value(stream_day::current().now()),
+            )
+            .filter(update_run::Column::Id.eq(self.id))
+            .exec(&db.db)
+            .await?;
+        self.albums_done.insert(id.to_owned());
+        Ok(())
+    }
+
+    /// Records the error an album failed with during the current stage.
+    pub(crate) async fn album_failed(
+        &self,
+        db: &DB,
+        album_id: &str,
+        message: &str,
+    ) -> Result<(), DbErr> {
+        update_run_error::ActiveModel {
+            run_id: Set(self.id),
+            stage: Set(self.stage.to_string()),
+            album_id: Set(album_id.to_owned()),
+            message: Set(message.to_owned()),
+            time: Set(stream_day::current().now()),
+            ..Default::default()
+        }
+        .insert(&db.db)
+        .await?;
+        Ok(())
+    }
+}
+
+/// The RunRow Struct summarises a daily update run for the run history.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RunRow {
+    id: i32,
+    date: NaiveDate,
+    stage: String,
+    started_at: DateTime<FixedOffset>,
+    ended_at: Option<DateTime<FixedOffset>>,
+    albums_fetched: usize,
+    albums_updated: usize,
+    tracks_updated: i32,
+    errors: u64,
+}
+
+/// The RunDisplay Struct holds a run's summary along with the time spent in each stage and the
+/// error each failed album was recorded with.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RunDisplay {
+    #[serde(flatten)]
+    run: RunRow,
+    stages: Vec<update_run_stage::Model>,
+    failures: Vec<update_run_error::Model>,
+}
+
+impl RunRow {
+    /// Creates the RunRow struct to be output
+    async fn create_row(db: &DB, model: update_run::Model) -> Result<Self, DbErr> {
+        let errors = model
+            .find_related(update_run_error::Entity)
+            .count(&db.db)
+            .await?;
+        Ok(Self {
+            id: model.id,
+            date: model.date,
+            stage: model.stage,
+            started_at: model.started_at,
+            ended_at: model.ended_at,
+            albums_fetched: model.album_ids.len(),
+            albums_updated: model.albums_done.len(),
+            tracks_updated: model.tracks_updated,
+            errors,
+        })
+    }
+
+    /// Creates a RunRow for each of the most recent runs, newest first.
+    pub(crate) async fn create_rows(db: &DB, limit: u64) -> Result<Vec<Self>, DbErr> {
+        let runs = update_run::Entity::find()
+            .order_by_desc(update_run::Column::Id)
+            .limit(limit)
+            .all(&db.db)
+            .await?;
+        let mut rows = Vec::with_capacity(runs.len());
+        for model in runs {
+            rows.push(RunRow::create_row(db, model).await?);
+        }
+        Ok(rows)
+    }
+}
+
+impl RunDisplay {
+    /// Creates the RunDisplay struct to be output
+    pub(crate) async fn create_run(db: &DB, id: i32) -> Result<Option<Self>, DbErr> {
+        let model = match update_run::Entity::find_by_id(id).one(&db.db).await? {
+            None => return Ok(None),
+            Some(value) => value,
+        };
+        let stages = model
+            .find_related(update_run_stage::Entity)
+            .order_by_asc(update_run_stage::Column::StartedAt)
+            .all(&db.db)
+            .await?;
+        let failures = model
+            .find_related(update_run_error::Entity)
+            .order_by_asc(update_run_error::Column::Id)
+            .all(&db.db)
+            .await?;
+        Ok(Some(Self {
+            run: RunRow::create_row(db, model).await?,
+            stages,
+            failures,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entity::{update_run, update_run_error, update_run_stage};
+    use crate::modules::config;
+    use crate::modules::data_base::DB;
+    use crate::modules::update_run::{RunDisplay, RunStage, UpdateRun};
+    use chrono::{DateTime, NaiveDate};
+    use sea_orm::{DatabaseBackend, MockDatabase, MockExecResult, Value};
+    use std::collections::{BTreeMap, HashSet};
+
+    fn count(value: i64) -> BTreeMap<&'static str, Value> {
+        BTreeMap::from([("num_items", value.into())])
+    }
+
+    #[test]
+    fn test_run_stage() {
+        for stage in [
+            RunStage::StatusCheck,
+            RunStage::Artists,
+            RunStage::Albums,
+            RunStage::Recordings,
+            RunStage::RemainingTracks,
+            RunStage::Complete,
+        ] {
+            assert_eq!(RunStage::parse(stage.as_str()), stage);
+        }
+        assert_eq!(RunStage::parse("unknown"), RunStage::StatusCheck);
+    }
+
+    #[tokio::test]
+    async fn test_run_display() {
+        let time = DateTime::parse_from_rfc3339("2023-11-10T06:30:00+00:00").unwrap();
+        let run = update_run::Model {
+            id: 7,
+            date: NaiveDate::from_ymd_opt(2023, 11, 9).unwrap(),
+            stage: RunStage::Complete.to_string(),
+            album_ids: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            albums_done: vec!["a".to_string(), "b".to_string()],
+            started_at: time,
+            updated_at: time,
+            ended_at: Some(time),
+            tracks_updated: 12,
+        };
+        let stage = update_run_stage::Model {
+            run_id: 7,
+            stage: RunStage::Albums.to_string(),
+            started_at: time,
+            ended_at: time,
+            duration_ms: 1500,
+        };
+        let failure = update_run_error::Model {
+            id: 1,
+            run_id: 7,
+            stage: RunStage::Albums.to_string(),
+            album_id: "c".to_string(),
+            message: "album c not found".to_string(),
+            time,
+        };
+        let db = DB {
+            db: MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[run]])
+                .append_query_results([[stage]])
+                .append_query_results([[failure]])
+                .append_query_results([[count(1)]])
+                .into_connection(),
+        };
+        let display = RunDisplay::create_run(&db, 7).await.unwrap().unwrap();
+        let json = serde_json::to_value(&display).unwrap();
+        assert_eq!(json["id"], 7);
+        assert_eq!(json["albums_fetched"], 3);
+        assert_eq!(json["albums_updated"], 2);
+        assert_eq!(json["tracks_updated"], 12);
+        assert_eq!(json["errors"], 1);
+        assert_eq!(json["stages"][0]["duration_ms"], 1500);
+        assert_eq!(json["failures"][0]["album_id"], "c");
+    }
+
+    #[tokio::test]
+    async fn test_record_stage() {
+        config::install_test();
+        let time = DateTime::parse_from_rfc3339("2023-11-10T06:30:00+00:00").unwrap();
+        let existing = update_run_stage::Model {
+            run_id: 7,
+            stage: RunStage::Albums.to_string(),
+            started_at: time,
+            ended_at: time,
+            duration_ms: 1500,
+        };
+        let db = DB {
+            db: MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[existing.clone()]])
+                .append_query_results([[existing]])
+                .append_query_results([[count(4)]])
+                .into_connection(),
+        };
+        let mut run = UpdateRun {
+            id: 7,
+            stage: RunStage::Albums,
+            album_ids: HashSet::new(),
+            albums_done: HashSet::new(),
+            date: NaiveDate::from_ymd_opt(2023, 11, 9).unwrap(),
+            started_at: time,
+            stage_started: time,
+            tracks_updated: 0,
+        };
+        run.record_stage(&db).await.unwrap();
+        assert_eq!(run.tracks_updated, 4);
+
+        let log = format!("{:?}", db.db.into_transaction_log());
+        assert!(log.contains(r#"\"time\" >= $"#));
+        assert!(log.contains("2023-11-10T06:30:00+00:00"));
+    }
+
+    #[tokio::test]
+    async fn test_album_done() {
+        config::install_test();
+        let time = DateTime::parse_from_rfc3339("2023-11-10T06:30:00+00:00").unwrap();
+        let db = DB {
+            db: MockDatabase::new(DatabaseBackend::Postgres)
+                .append_exec_results([MockExecResult {
+                    last_insert_id: 0,
+                    rows_affected: 1,
+                }])
+                .into_connection(),
+        };
+        let mut run = UpdateRun {
+            id: 7,
+            stage: RunStage::Albums,
+            album_ids: HashSet::from(["a".to_string(), "b".to_string()]),
+            albums_done: HashSet::new(),
+            date: NaiveDate::from_ymd_opt(2023, 11, 9).unwrap(),
+            started_at: time,
+            stage_started: time,
+            tracks_updated: 0,
+        };
+        run.album_done(&db, "a").await.unwrap();
+        assert!(run.albums_done.contains("a"));
+
+        let log = format!("{:?}", db.db.into_transaction_log());
+        assert!(log.contains(r#"array_append(\"albums_done\", $1)"#));
+        assert!(log.contains(r#"String(Some("a"))"#));
+        assert!(!log.contains(r#"\"stage\""#));
+    }
+}
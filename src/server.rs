@@ -1,96 +1,390 @@
+use chrono::NaiveDate;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Status};
 use rocket::serde::json::Json;
 use rocket::*;
-use sea_orm::DbErr;
-use stream_accumulator::entity::artist;
-use stream_accumulator::modules::artist_display::{AlbumDisplay, ArtistDisplay};
-use stream_accumulator::modules::data_base::DB;
+use stream_accumulator::entity::{artist, stream_quarantine};
+use stream_accumulator::modules::artist_display::{
+    AlbumDisplay, ArtistDisplay, CityDisplay, LeaderboardRow, LeaderboardSort, ListenerRow,
+    RecordingDisplay, StreamPoint,
+};
+use stream_accumulator::modules::artist_policy::{PolicyDisplay, PolicyList};
+use stream_accumulator::modules::data_base::{TrackGaps, DB};
+use stream_accumulator::modules::error::AccumulatorError;
+use stream_accumulator::modules::update_run::{RunDisplay, RunRow};
+use stream_accumulator::modules::{config, logging, metrics};
+use tokio::time::Instant;
+use tracing::{info, instrument};
 
 #[derive(Responder)]
-#[response(status = 500, content_type = "json")]
+#[response(content_type = "json")]
 struct ErrorResponder {
-    message: String,
+    message: (Status, String),
 }
 
-impl From<DbErr> for ErrorResponder {
-    fn from(err: DbErr) -> ErrorResponder {
+impl From<AccumulatorError> for ErrorResponder {
+    fn from(err: AccumulatorError) -> ErrorResponder {
+        let status = match err {
+            AccumulatorError::NotFound(_) => Status::NotFound,
+            AccumulatorError::Validation(_) => Status::BadRequest,
+            AccumulatorError::Policy(_) => Status::Forbidden,
+            AccumulatorError::RateLimited { .. } => Status::ServiceUnavailable,
+            AccumulatorError::Http { .. }
+            | AccumulatorError::Auth(_)
+            | AccumulatorError::Parse(_) => Status::BadGateway,
+            AccumulatorError::Db(_) | AccumulatorError::Interrupted => Status::InternalServerError,
+        };
         ErrorResponder {
-            message: err.to_string(),
+            message: (status, err.to_string()),
         }
     }
 }
 
-impl From<String> for ErrorResponder {
-    fn from(string: String) -> ErrorResponder {
-        ErrorResponder { message: string }
+/// The RequestLog fairing records the method, route, status, and latency of every request, both
+/// logged and as the http_request_duration_seconds histogram.
+struct RequestLog;
+
+#[rocket::async_trait]
+impl Fairing for RequestLog {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request log",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(Instant::now);
     }
-}
 
-impl From<&str> for ErrorResponder {
-    fn from(str: &str) -> ErrorResponder {
-        str.to_owned().into()
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let elapsed = request.local_cache(Instant::now).elapsed();
+        let route = request
+            .route()
+            .and_then(|route| route.name.as_deref())
+            .unwrap_or("unmatched");
+        metrics::shared()
+            .http_requests
+            .with_label_values(&[
+                request.method().as_str(),
+                route,
+                &response.status().code.to_string(),
+            ])
+            .observe(elapsed.as_secs_f64());
+        info!(
+            method = %request.method(),
+            uri = %request.uri(),
+            route,
+            status = response.status().code,
+            latency_ms = elapsed.as_millis() as u64,
+            "request handled"
+        );
     }
 }
 
+#[get("/metrics")]
+fn metrics_text() -> (ContentType, String) {
+    (
+        ContentType::parse_flexible(metrics::CONTENT_TYPE).unwrap_or(ContentType::Plain),
+        metrics::gather(),
+    )
+}
+
 #[post("/artists/create/<id>")]
+#[instrument(skip_all, fields(artist_id = id))]
 async fn create_artist(db: &State<DB>, id: &str) -> Result<Json<artist::Model>, ErrorResponder> {
     let db = db as &DB;
-    match db.create_artist(id).await {
-        Some(value) => Ok(Json(value)),
-        _ => Err(ErrorResponder::from("Artist not created")),
-    }
+    Ok(Json(db.create_artist(id).await?))
 }
 
 #[post("/artists/delete/<id>")]
+#[instrument(skip_all, fields(artist_id = id))]
 async fn delete_artist(db: &State<DB>, id: &str) -> Result<String, ErrorResponder> {
     let db = db as &DB;
-    match db.delete_artist(id).await {
-        Ok(true) => Ok(format!("Artist {} deleted", id)),
-        _ => Err(ErrorResponder::from("Artist not deleted")),
-    }
+    db.delete_artist(id).await?;
+    Ok(format!("Artist {} deleted", id))
 }
 #[get("/artists")]
-async fn artists(db: &State<DB>) -> Json<Vec<artist::Model>> {
+#[instrument(skip_all)]
+async fn artists(db: &State<DB>) -> Result<Json<Vec<artist::Model>>, ErrorResponder> {
     let db = db as &DB;
     let artists = db
         .get_all_artists_standard(|artists: Vec<artist::Model>| artists)
-        .await
-        .unwrap();
-    Json(artists)
+        .await?;
+    Ok(Json(artists))
+}
+
+#[get("/artists/leaderboard?<sort>")]
+#[instrument(skip_all, fields(sort))]
+async fn leaderboard(
+    db: &State<DB>,
+    sort: Option<&str>,
+) -> Result<Json<Vec<LeaderboardRow>>, ErrorResponder> {
+    let db = db as &DB;
+    Ok(Json(
+        db.get_leaderboard(LeaderboardSort::parse(sort)?).await?,
+    ))
 }
 
 #[get("/artists/display/<id>")]
-async fn artist_display(id: &str) -> Result<Json<ArtistDisplay>, ErrorResponder> {
-    match DB::get_artist_for_display(id).await {
-        Ok(Some(value)) => Ok(Json(value)),
-        _ => Err(ErrorResponder::from(format!(
-            "Error fetching artist {}",
-            id
-        ))),
+#[instrument(skip_all, fields(artist_id = id))]
+async fn artist_display(db: &State<DB>, id: &str) -> Result<Json<ArtistDisplay>, ErrorResponder> {
+    let db = db as &DB;
+    match db.get_artist_for_display(id).await? {
+        Some(value) => Ok(Json(value)),
+        None => Err(AccumulatorError::NotFound(format!("artist {}", id)).into()),
     }
 }
 
-#[get("/album/display/<id>")]
-async fn album_display(id: &str) -> Result<Json<AlbumDisplay>, ErrorResponder> {
-    match DB::get_album_for_display(id).await {
-        Ok(value) => Ok(Json(value)),
-        _ => Err(ErrorResponder::from(format!("Error fetching album {}", id))),
+#[get("/artists/<id>/listeners", rank = 2)]
+#[instrument(skip_all, fields(artist_id = id))]
+async fn artist_listeners(
+    db: &State<DB>,
+    id: &str,
+) -> Result<Json<Vec<ListenerRow>>, ErrorResponder> {
+    let db = db as &DB;
+    Ok(Json(db.get_listener_history(id).await?))
+}
+
+#[get("/artists/cities/<id>")]
+#[instrument(skip_all, fields(artist_id = id))]
+async fn artist_cities(db: &State<DB>, id: &str) -> Result<Json<Vec<CityDisplay>>, ErrorResponder> {
+    let db = db as &DB;
+    Ok(Json(db.get_city_history(id).await?))
+}
+
+#[get("/recordings/<isrc>")]
+#[instrument(skip_all, fields(isrc))]
+async fn recording_display(
+    db: &State<DB>,
+    isrc: &str,
+) -> Result<Json<RecordingDisplay>, ErrorResponder> {
+    let db = db as &DB;
+    match db.get_recording_for_display(isrc).await? {
+        Some(value) => Ok(Json(value)),
+        None => Err(AccumulatorError::NotFound(format!("recording {}", isrc)).into()),
+    }
+}
+
+#[get("/recordings/<isrc>/streams")]
+#[instrument(skip_all, fields(isrc))]
+async fn recording_streams(
+    db: &State<DB>,
+    isrc: &str,
+) -> Result<Json<Vec<StreamPoint>>, ErrorResponder> {
+    let db = db as &DB;
+    Ok(Json(db.get_recording_streams(isrc).await?))
+}
+
+#[get("/admin/quarantine")]
+#[instrument(skip_all)]
+async fn quarantined(
+    db: &State<DB>,
+) -> Result<Json<Vec<stream_quarantine::Model>>, ErrorResponder> {
+    let db = db as &DB;
+    Ok(Json(db.get_quarantined().await?))
+}
+
+#[post("/admin/quarantine/<track_id>/<date>/<decision>")]
+#[instrument(skip_all, fields(track_id, date, decision))]
+async fn review_quarantined(
+    db: &State<DB>,
+    track_id: &str,
+    date: &str,
+    decision: &str,
+) -> Result<String, ErrorResponder> {
+    let db = db as &DB;
+    let accept = match decision {
+        "accept" => true,
+        "reject" => false,
+        _ => {
+            return Err(AccumulatorError::Validation(format!(
+                "decision {} must be accept or reject",
+                decision
+            ))
+            .into())
+        }
+    };
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| AccumulatorError::Validation(format!("date {}", date)))?;
+    match db.review_quarantined(track_id, date, accept).await? {
+        true => Ok(format!(
+            "Streams for {} on {} {}ed",
+            track_id, date, decision
+        )),
+        false => Err(AccumulatorError::NotFound(format!(
+            "pending streams for {} on {}",
+            track_id, date
+        ))
+        .into()),
     }
 }
 
+#[get("/admin/policy")]
+#[instrument(skip_all)]
+async fn policy(db: &State<DB>) -> Result<Json<PolicyDisplay>, ErrorResponder> {
+    let db = db as &DB;
+    Ok(Json(db.get_policy().await?))
+}
+
+#[post("/admin/policy/<list>/<id>/add?<reason>")]
+#[instrument(skip_all, fields(list, artist_id = id))]
+async fn add_to_policy(
+    db: &State<DB>,
+    list: &str,
+    id: &str,
+    reason: Option<String>,
+) -> Result<String, ErrorResponder> {
+    let db = db as &DB;
+    let list = PolicyList::parse(list)?;
+    db.add_to_policy(list, id, reason).await?;
+    Ok(format!("Artist {} added to {}", id, list))
+}
+
+#[post("/admin/policy/<list>/<id>/remove")]
+#[instrument(skip_all, fields(list, artist_id = id))]
+async fn remove_from_policy(
+    db: &State<DB>,
+    list: &str,
+    id: &str,
+) -> Result<String, ErrorResponder> {
+    let db = db as &DB;
+    let list = PolicyList::parse(list)?;
+    match db.remove_from_policy(list, id).await? {
+        true => Ok(format!("Artist {} removed from {}", id, list)),
+        false => Err(AccumulatorError::NotFound(format!("artist {} on {}", id, list)).into()),
+    }
+}
+
+#[get("/admin/gaps")]
+#[instrument(skip_all)]
+async fn gaps(db: &State<DB>) -> Result<Json<Vec<TrackGaps>>, ErrorResponder> {
+    let db = db as &DB;
+    Ok(Json(db.scan_gaps(false).await?))
+}
+
+#[post("/admin/gaps/fill")]
+#[instrument(skip_all)]
+async fn fill_gaps(db: &State<DB>) -> Result<Json<Vec<TrackGaps>>, ErrorResponder> {
+    let db = db as &DB;
+    Ok(Json(db.scan_gaps(true).await?))
+}
+
+#[get("/runs?<limit>")]
+#[instrument(skip_all, fields(limit))]
+async fn runs(db: &State<DB>, limit: Option<u64>) -> Result<Json<Vec<RunRow>>, ErrorResponder> {
+    let db = db as &DB;
+    Ok(Json(db.get_runs(limit).await?))
+}
+
+#[get("/runs/<id>")]
+#[instrument(skip_all, fields(run_id = id))]
+async fn run_display(db: &State<DB>, id: i32) -> Result<Json<RunDisplay>, ErrorResponder> {
+    let db = db as &DB;
+    match db.get_run_for_display(id).await? {
+        Some(value) => Ok(Json(value)),
+        None => Err(AccumulatorError::NotFound(format!("run {}", id)).into()),
+    }
+}
+
+#[get("/album/display/<id>")]
+#[instrument(skip_all, fields(album_id = id))]
+async fn album_display(db: &State<DB>, id: &str) -> Result<Json<AlbumDisplay>, ErrorResponder> {
+    let db = db as &DB;
+    Ok(Json(db.get_album_for_display(id).await?))
+}
+
 #[launch]
 pub async fn rocket() -> Rocket<Build> {
+    let config = match config::install_from_args() {
+        Ok(config) => config,
+        Err(error) => panic!("{}", error),
+    };
+    logging::init(&config.logging);
     let db = match DB::create().await {
         Ok(db) => db,
         Err(error) => panic!("error with database: {}", error),
     };
-    build().manage(db).mount(
+    let figment = Config::figment()
+        .merge(("address", config.server.address.as_str()))
+        .merge(("port", config.server.port));
+    mount(custom(figment), db)
+}
+
+/// Mounts every route on the rocket provided, managing the database given.
+fn mount(rocket: Rocket<Build>, db: DB) -> Rocket<Build> {
+    rocket.attach(RequestLog).manage(db).mount(
         "/",
         routes![
             artists,
             create_artist,
             delete_artist,
             artist_display,
-            album_display
+            artist_listeners,
+            artist_cities,
+            leaderboard,
+            album_display,
+            recording_display,
+            recording_streams,
+            quarantined,
+            review_quarantined,
+            gaps,
+            fill_gaps,
+            policy,
+            add_to_policy,
+            remove_from_policy,
+            runs,
+            run_display,
+            metrics_text
         ],
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::mount;
+    use chrono::NaiveDate;
+    use rocket::http::Status;
+    use rocket::local::asynchronous::Client;
+    use sea_orm::{DatabaseBackend, DatabaseConnection, MockDatabase};
+    use stream_accumulator::entity::{follower_instance, monthly_listeners};
+    use stream_accumulator::modules::data_base::DB;
+
+    #[rocket::async_test]
+    async fn test_routes_ignite() {
+        let db = DB {
+            db: DatabaseConnection::Disconnected,
+        };
+        let client = Client::tracked(mount(rocket::build(), db)).await;
+        assert!(client.is_ok(), "{:?}", client.err());
+    }
+
+    #[rocket::async_test]
+    async fn test_artist_listeners() {
+        let date = NaiveDate::from_ymd_opt(2023, 11, 9).unwrap();
+        let db = DB {
+            db: MockDatabase::new(DatabaseBackend::Postgres)
+                .append_query_results([[monthly_listeners::Model {
+                    date,
+                    artist_id: "06HL4z0CvFAxyc27GXpf02".to_string(),
+                    listeners: 82713043,
+                }]])
+                .append_query_results([[follower_instance::Model {
+                    artist_id: "06HL4z0CvFAxyc27GXpf02".to_string(),
+                    date,
+                    count: 95000000,
+                }]])
+                .into_connection(),
+        };
+        let client = Client::tracked(mount(rocket::build(), db)).await.unwrap();
+        let response = client
+            .get("/artists/06HL4z0CvFAxyc27GXpf02/listeners")
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let rows = response.into_json::<serde_json::Value>().await.unwrap();
+        assert_eq!(rows[0]["date"], "2023-11-09");
+        assert_eq!(rows[0]["listeners"], 82713043);
+        assert_eq!(rows[0]["followers"], 95000000);
+    }
+}